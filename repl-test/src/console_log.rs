@@ -25,7 +25,7 @@ impl ConsoleLogger {
 
     pub(crate) fn format_log_record(io: &mut (dyn Write + Send), record: &Record, colored: bool) {
         let target = {
-            if let Some(target_str) = record.target().split(':').last() {
+            if let Some(target_str) = record.target().split(':').next_back() {
                 if let Some(line) = record.line() {
                     format!(" ({target_str}:{line})")
                 } else {
@@ -13,8 +13,8 @@
 //! in your terminal
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use rustyrepl::{Repl, ReplCommandProcessor};
+use clap::Subcommand;
+use rustyrepl::{AsyncReplCommandProcessor, Repl};
 
 mod console_log;
 
@@ -29,24 +29,16 @@ pub enum Command {
     Test,
 }
 
-/// The general CLI, essentially a wrapper for the sub-commands [Command]
-#[derive(Parser, Clone, Debug)]
-pub struct Cli {
-    #[clap(subcommand)]
-    command: Command,
-}
-
 #[derive(Debug)]
 pub struct CliProcessor {}
 
-#[async_trait::async_trait]
-impl ReplCommandProcessor<Cli> for CliProcessor {
+impl AsyncReplCommandProcessor<Command> for CliProcessor {
     fn is_quit(&self, command: &str) -> bool {
         matches!(command, "quit" | "exit")
     }
 
-    async fn process_command(&self, command: Cli) -> Result<()> {
-        match command.command {
+    async fn process_command(&self, command: Command) -> Result<()> {
+        match command {
             Command::Test => println!("A wild test appeared!"),
         }
         Ok(())
@@ -63,11 +55,15 @@ async fn main() -> Result<()> {
         .map(|()| log::set_max_level(LOGGER.level.to_level_filter()))
         .expect("Failed to set up logging");
 
-    let processor: Box<dyn ReplCommandProcessor<Cli>> = Box::new(CliProcessor {});
+    let processor = CliProcessor {};
     // create a dummy test history file
     let history_file = ".test_history".to_string();
     let some_history_file = Some(history_file);
 
-    let mut repl = Repl::<Cli>::new(processor, some_history_file, Some(">> ".to_string()))?;
-    repl.process().await
+    let mut repl =
+        Repl::<Command, _>::from_subcommand(processor, some_history_file, Some(">> ".to_string()))?
+            .with_blocking_spawner(|job| {
+                tokio::task::spawn_blocking(job);
+            });
+    repl.process_async().await
 }
@@ -0,0 +1,58 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! A REPL whose command set is assembled at runtime with the [clap::Command] builder API,
+//! as would be done when loading commands from configuration or plugins. Run it with
+//!
+//! ```bash
+//! cargo run --example builder
+//! ```
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+use rustyrepl::{Repl, ReplCommandProcessor};
+
+/// The greetings which are turned into sub-commands, e.g. read from a config file
+const GREETINGS: &[(&str, &str)] = &[("hello", "Hello"), ("bonjour", "Bonjour")];
+
+#[derive(Debug)]
+pub struct GreetingProcessor {}
+
+impl ReplCommandProcessor<ArgMatches> for GreetingProcessor {
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+
+    fn process_command(&self, command: ArgMatches) -> Result<()> {
+        if let Some((name, matches)) = command.subcommand() {
+            let greeting = GREETINGS
+                .iter()
+                .find(|(command, _)| *command == name)
+                .map(|(_, greeting)| *greeting)
+                .unwrap_or("Hi");
+            let who = matches.get_one::<String>("who").map(String::as_str);
+            println!("{}, {}!", greeting, who.unwrap_or("world"));
+        }
+        Ok(())
+    }
+}
+
+// MAIN //
+fn main() -> Result<()> {
+    let command = GREETINGS.iter().fold(
+        Command::new("greeter").subcommand_required(true),
+        |command, (name, greeting)| {
+            command.subcommand(
+                Command::new(*name)
+                    .about(format!("Say \"{}\"", greeting))
+                    .arg(Arg::new("who").help("Who to greet")),
+            )
+        },
+    );
+
+    let mut repl =
+        Repl::from_command(command, GreetingProcessor {}, None, Some(">> ".to_string()))?;
+    repl.process()
+}
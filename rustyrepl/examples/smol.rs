@@ -0,0 +1,47 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! An asynchronous REPL driven by smol. Run it with
+//!
+//! ```bash
+//! cargo run --example smol
+//! ```
+
+use anyhow::Result;
+use clap::Subcommand;
+use rustyrepl::{AsyncReplCommandProcessor, Repl};
+
+/// The enum of sub-commands supported by the CLI
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Execute a test command
+    Test,
+}
+
+#[derive(Debug)]
+pub struct CliProcessor {}
+
+impl AsyncReplCommandProcessor<Command> for CliProcessor {
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+
+    async fn process_command(&self, command: Command) -> Result<()> {
+        match command {
+            Command::Test => println!("A wild test appeared on smol!"),
+        }
+        Ok(())
+    }
+}
+
+// MAIN //
+fn main() -> Result<()> {
+    smol::block_on(async {
+        let mut repl =
+            Repl::<Command, _>::from_subcommand(CliProcessor {}, None, Some(">> ".to_string()))?
+                .with_blocking_spawner(|job| smol::unblock(job).detach());
+        repl.process_async().await
+    })
+}
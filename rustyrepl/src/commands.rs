@@ -6,15 +6,64 @@
 //! Represents a processor of REPL commands from a user's Clap parsed input
 
 use anyhow::Result;
+use std::future::Future;
 
-#[cfg(feature = "async")]
-#[cfg_attr(feature = "async", async_trait::async_trait)]
-pub trait ReplCommandProcessor<C>: std::fmt::Debug
-where
-    C: clap::Parser,
-{
-    /// Process the supplied command which is a clap::Parser structure
-    async fn process_command(&self, command: C) -> Result<()>;
+use crate::console::Console;
+use crate::context::ReplContext;
+use crate::render::Render;
+
+/// A synchronous processor of REPL commands, driven by [crate::Repl::process]
+///
+/// `C` is the parsed command, either a [clap::Parser] structure, a [clap::Subcommand] enum or,
+/// for REPLs built from a [clap::Command] at runtime, the resulting [clap::ArgMatches]
+pub trait ReplCommandProcessor<C>: std::fmt::Debug {
+    /// Process the supplied parsed command
+    fn process_command(&self, command: C) -> Result<()>;
+
+    /// Process the supplied parsed command with a handle to the REPL's [Console], for commands
+    /// which ask the user questions (i.e. a password) or write output while processing. The
+    /// REPL always calls this, which forwards to `process_command` by default. Processors
+    /// handling every command here may return an error from `process_command`
+    fn process_command_with(&self, command: C, console: &Console) -> Result<()> {
+        let _ = console;
+        self.process_command(command)
+    }
+
+    /// Process the supplied parsed command, returning its result (i.e. anything
+    /// [serde::Serialize]) for the REPL to render in the session's [crate::OutputFormat] and
+    /// keep for later commands to reference with `$_`. The REPL always calls this, which
+    /// forwards to `process_command_with` and returns no result by default
+    fn process_command_result(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> Result<Option<Box<dyn Render>>> {
+        self.process_command_with(command, console).map(|()| None)
+    }
+
+    /// Handle a raw line which didn't parse as a command, e.g. to evaluate bare text as an
+    /// expression, run it as a query or send it as a message. Returns whether the line was
+    /// handled, with the parse error only displayed for declined lines. Declines every line
+    /// by default
+    fn process_unparsed(&self, line: &str) -> Result<bool> {
+        let _ = line;
+        Ok(false)
+    }
+
+    /// The nested context entered by the command (i.e. `use db orders`), if any. Commands
+    /// entering a context aren't processed, instead the REPL switches to the context's command
+    /// set, prompt and history until the user leaves it with `exit` or `end`. None by default
+    fn enter_context(&self, command: &C) -> Option<ReplContext> {
+        let _ = command;
+        None
+    }
+
+    /// Whether the command is destructive, so the user is asked `Are you sure? [y/N]` before
+    /// it's processed. Adding `--yes` to the line skips the question. None are by default
+    fn requires_confirmation(&self, command: &C) -> bool {
+        let _ = command;
+        false
+    }
 
     /// Determine if the supplied command is a "quit" operation. This will be
     /// formatted into a trimmed string of lowercase letters. Example matching might
@@ -28,13 +77,101 @@ where
     fn is_quit(&self, command: &str) -> bool;
 }
 
-#[cfg(not(feature = "async"))]
-pub trait ReplCommandProcessor<C>: std::fmt::Debug
+impl<C, T> ReplCommandProcessor<C> for Box<T>
 where
-    C: clap::Parser,
+    T: ReplCommandProcessor<C> + ?Sized,
 {
-    /// Process the supplied command which is a clap::Parser structure
-    fn process_command(&self, command: C) -> Result<()>;
+    fn process_command(&self, command: C) -> Result<()> {
+        (**self).process_command(command)
+    }
+
+    fn process_command_with(&self, command: C, console: &Console) -> Result<()> {
+        (**self).process_command_with(command, console)
+    }
+
+    fn process_command_result(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> Result<Option<Box<dyn Render>>> {
+        (**self).process_command_result(command, console)
+    }
+
+    fn process_unparsed(&self, line: &str) -> Result<bool> {
+        (**self).process_unparsed(line)
+    }
+
+    fn enter_context(&self, command: &C) -> Option<ReplContext> {
+        (**self).enter_context(command)
+    }
+
+    fn requires_confirmation(&self, command: &C) -> bool {
+        (**self).requires_confirmation(command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        (**self).is_quit(command)
+    }
+}
+
+/// An asynchronous processor of REPL commands, driven by [crate::Repl::process_async].
+///
+/// Implementations can simply write `async fn process_command(&self, command: C) -> Result<()>`.
+/// The returned future is polled in place by the REPL loop, so it isn't boxed per-command and
+/// isn't required to be [Send], allowing state such as `Rc` handles on single-threaded executors
+pub trait AsyncReplCommandProcessor<C>: std::fmt::Debug {
+    /// Process the supplied parsed command
+    fn process_command(&self, command: C) -> impl Future<Output = Result<()>>;
+
+    /// Process the supplied parsed command with a handle to the REPL's [Console], for commands
+    /// which ask the user questions (i.e. a password) or write output while processing. The
+    /// REPL always calls this, which forwards to `process_command` by default. Processors
+    /// handling every command here may return an error from `process_command`
+    fn process_command_with(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> impl Future<Output = Result<()>> {
+        let _ = console;
+        self.process_command(command)
+    }
+
+    /// Process the supplied parsed command, returning its result (i.e. anything
+    /// [serde::Serialize]) for the REPL to render in the session's [crate::OutputFormat] and
+    /// keep for later commands to reference with `$_`. The REPL always calls this, which
+    /// forwards to `process_command_with` and returns no result by default
+    fn process_command_result(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> impl Future<Output = Result<Option<Box<dyn Render>>>> {
+        let processed = self.process_command_with(command, console);
+        async move { processed.await.map(|()| None) }
+    }
+
+    /// Handle a raw line which didn't parse as a command, e.g. to evaluate bare text as an
+    /// expression, run it as a query or send it as a message. Resolves to whether the line was
+    /// handled, with the parse error only displayed for declined lines. Declines every line
+    /// by default
+    fn process_unparsed(&self, line: &str) -> impl Future<Output = Result<bool>> {
+        let _ = line;
+        async { Ok(false) }
+    }
+
+    /// The nested context entered by the command (i.e. `use db orders`), if any. Commands
+    /// entering a context aren't processed, instead the REPL switches to the context's command
+    /// set, prompt and history until the user leaves it with `exit` or `end`. None by default
+    fn enter_context(&self, command: &C) -> Option<ReplContext> {
+        let _ = command;
+        None
+    }
+
+    /// Whether the command is destructive, so the user is asked `Are you sure? [y/N]` before
+    /// it's processed. Adding `--yes` to the line skips the question. None are by default
+    fn requires_confirmation(&self, command: &C) -> bool {
+        let _ = command;
+        false
+    }
 
     /// Determine if the supplied command is a "quit" operation. This will be
     /// formatted into a trimmed string of lowercase letters. Example matching might
@@ -47,3 +184,44 @@ where
     /// ```
     fn is_quit(&self, command: &str) -> bool;
 }
+
+impl<C, T> AsyncReplCommandProcessor<C> for Box<T>
+where
+    T: AsyncReplCommandProcessor<C> + ?Sized,
+{
+    fn process_command(&self, command: C) -> impl Future<Output = Result<()>> {
+        (**self).process_command(command)
+    }
+
+    fn process_command_with(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> impl Future<Output = Result<()>> {
+        (**self).process_command_with(command, console)
+    }
+
+    fn process_command_result(
+        &self,
+        command: C,
+        console: &Console,
+    ) -> impl Future<Output = Result<Option<Box<dyn Render>>>> {
+        (**self).process_command_result(command, console)
+    }
+
+    fn process_unparsed(&self, line: &str) -> impl Future<Output = Result<bool>> {
+        (**self).process_unparsed(line)
+    }
+
+    fn enter_context(&self, command: &C) -> Option<ReplContext> {
+        (**self).enter_context(command)
+    }
+
+    fn requires_confirmation(&self, command: &C) -> bool {
+        (**self).requires_confirmation(command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        (**self).is_quit(command)
+    }
+}
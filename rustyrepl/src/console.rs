@@ -0,0 +1,268 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Questions put to the user while a command is running, read through the REPL's own input
+//! so they never conflict with the line editor
+
+use anyhow::{bail, Result};
+use log::warn;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::output::{HeldOutput, OutputStream, ReplOutput};
+use crate::render::{OutputFormat, Render};
+use crate::repl::Input;
+use crate::runtime::Spawner;
+
+/// The question asked before processing a command requiring confirmation
+pub(crate) const CONFIRMATION_QUESTION: &str = "Are you sure?";
+
+/// How confirmations are answered when the REPL is running a script, where there may be
+/// nobody to ask
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptConfirmation {
+    /// Answer no, so commands requiring confirmation are skipped (the default)
+    #[default]
+    Decline,
+    /// Answer yes, so commands requiring confirmation are run
+    Accept,
+    /// Read the answer from the script's next line
+    Read,
+}
+
+/// A handle for asking the user questions, through the same editor (or script) the REPL
+/// reads commands from, and for writing output to wherever the REPL's output goes. Answers
+/// aren't added to the command history, and when running a script they're read from the
+/// script's following lines. Synchronous processors ask with the `_blocking` variants (i.e.
+/// [Console::ask_blocking]).
+///
+/// ```
+/// use anyhow::Result;
+/// use rustyrepl::{AsyncReplCommandProcessor, Console};
+/// use std::io::Write;
+///
+/// #[derive(Debug)]
+/// struct LoginProcessor;
+///
+/// impl AsyncReplCommandProcessor<String> for LoginProcessor {
+///     async fn process_command(&self, _user: String) -> Result<()> {
+///         anyhow::bail!("Logging in requires a console")
+///     }
+///
+///     async fn process_command_with(&self, user: String, console: &Console) -> Result<()> {
+///         let password = console.password(&format!("Password for {}: ", user)).await?;
+///         let region = console.select("Region", &["us-east-1", "eu-west-1"]).await?;
+///         anyhow::ensure!(!password.is_empty(), "A password is required");
+///         writeln!(console.out(), "Logged in to region {} as {}", region, user)?;
+///         Ok(())
+///     }
+///
+///     fn is_quit(&self, command: &str) -> bool {
+///         matches!(command, "quit")
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Console {
+    input: Input,
+    /// Where reads are run when asked from the asynchronous loop, otherwise they block in place
+    spawner: Option<Spawner>,
+    /// How confirmations are answered when running a script
+    script_confirmation: ScriptConfirmation,
+    output: ReplOutput,
+    /// The format results are rendered in
+    format: OutputFormat,
+    /// The last result rendered, which the REPL keeps once the command completes
+    result: Arc<Mutex<Option<Value>>>,
+    /// Whether the line was run with [crate::parser::CONFIRMATION_BYPASS_FLAG]
+    confirmation_bypassed: bool,
+    /// The line's output held back to page it, which is written out before asking a question
+    held: Option<HeldOutput>,
+}
+
+impl Console {
+    pub(crate) fn new(
+        input: Input,
+        spawner: Option<Spawner>,
+        script_confirmation: ScriptConfirmation,
+        output: ReplOutput,
+        format: OutputFormat,
+        result: Arc<Mutex<Option<Value>>>,
+    ) -> Self {
+        Self {
+            input,
+            spawner,
+            script_confirmation,
+            output,
+            format,
+            result,
+            confirmation_bypassed: false,
+            held: None,
+        }
+    }
+
+    /// The console for a line whose output is held back to page it
+    pub(crate) fn with_held_output(mut self, held: Option<HeldOutput>) -> Self {
+        self.held = held;
+        self
+    }
+
+    /// The console for a line run with (or without) the confirmation bypass flag
+    pub(crate) fn with_confirmation_bypassed(mut self, bypassed: bool) -> Self {
+        self.confirmation_bypassed = bypassed;
+        self
+    }
+
+    /// Whether the line was run with `--yes`, so confirmations are answered yes without
+    /// asking
+    pub fn confirmation_bypassed(&self) -> bool {
+        self.confirmation_bypassed
+    }
+
+    /// The stdout-like stream for command output, which is the terminal unless the REPL's
+    /// output was captured or redirected with [crate::Repl::with_output]
+    pub fn out(&self) -> OutputStream {
+        self.output.out()
+    }
+
+    /// The stderr-like stream for diagnostics
+    pub fn err(&self) -> OutputStream {
+        self.output.err()
+    }
+
+    /// The format results are rendered in, set with `:format`
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Render the command's result (i.e. anything [serde::Serialize]) in the session's
+    /// [OutputFormat] to the output stream, rather than the command formatting it itself. The
+    /// result is kept for later commands to reference with `$_`
+    pub fn render<T: Render + ?Sized>(&self, result: &T) -> Result<()> {
+        let rendered = result.render(self.format)?;
+        writeln!(self.out(), "{}", rendered)?;
+        let value = result.to_value()?;
+        *self
+            .result
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(value);
+        Ok(())
+    }
+
+    /// Render the result a command returned, if any. See [Console::render]
+    pub(crate) fn render_returned(&self, result: Option<Box<dyn Render>>) -> Result<()> {
+        match result {
+            Some(result) => self.render(&*result),
+            None => Ok(()),
+        }
+    }
+
+    /// Ask the user a question, returning their answer without the trailing newline
+    pub async fn ask(&self, question: &str) -> Result<String> {
+        self.read(question, false).await
+    }
+
+    /// Ask the user for a secret (i.e. a password), which is masked as it's typed
+    pub async fn password(&self, question: &str) -> Result<String> {
+        self.read(question, true).await
+    }
+
+    /// Ask the user to choose one of the options, which are listed by number. Either the
+    /// number or the option itself is accepted, and the index of the chosen option returned.
+    /// An invalid choice is asked again, except in a script where it's an error
+    pub async fn select<S: AsRef<str>>(&self, question: &str, options: &[S]) -> Result<usize> {
+        if options.is_empty() {
+            bail!("There are no options to select from");
+        }
+        for (number, option) in options.iter().enumerate() {
+            // listed with the diagnostics, so they're shown even when the output is captured
+            writeln!(self.err(), "  {}) {}", number + 1, option.as_ref())?;
+        }
+        let question = format!("{} [1-{}] ", question, options.len());
+        loop {
+            let answer = self.ask(&question).await?;
+            let answer = answer.trim();
+            let chosen = match answer.parse::<usize>() {
+                Ok(number) => number.checked_sub(1).filter(|index| *index < options.len()),
+                Err(_) => options
+                    .iter()
+                    .position(|option| option.as_ref().eq_ignore_ascii_case(answer)),
+            };
+            match chosen {
+                Some(index) => return Ok(index),
+                None if self.input.is_script() => {
+                    bail!("'{}' isn't one of the options", answer)
+                }
+                None => warn!("'{}' isn't one of the options", answer),
+            }
+        }
+    }
+
+    /// Ask the user a yes or no question, where anything other than "y" or "yes" (in any
+    /// case) is a no. A line run with `--yes` is answered yes without asking, and when
+    /// running a script, the question is answered according to the REPL's
+    /// [ScriptConfirmation] policy
+    pub async fn confirm(&self, question: &str) -> Result<bool> {
+        if self.confirmation_bypassed {
+            return Ok(true);
+        }
+        if self.input.is_script() {
+            match self.script_confirmation {
+                ScriptConfirmation::Decline => return Ok(false),
+                ScriptConfirmation::Accept => return Ok(true),
+                ScriptConfirmation::Read => {}
+            }
+        }
+        let answer = self.ask(&format!("{} [y/N] ", question)).await?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    /// Ask the user a question from a synchronous processor, blocking until it's answered.
+    /// See [Console::ask]
+    pub fn ask_blocking(&self, question: &str) -> Result<String> {
+        crate::runtime::block_on(self.ask(question))
+    }
+
+    /// Ask the user for a secret from a synchronous processor, blocking until it's answered.
+    /// See [Console::password]
+    pub fn password_blocking(&self, question: &str) -> Result<String> {
+        crate::runtime::block_on(self.password(question))
+    }
+
+    /// Ask the user to choose one of the options from a synchronous processor, blocking until
+    /// they have. See [Console::select]
+    pub fn select_blocking<S: AsRef<str>>(&self, question: &str, options: &[S]) -> Result<usize> {
+        crate::runtime::block_on(self.select(question, options))
+    }
+
+    /// Ask the user a yes or no question from a synchronous processor, blocking until it's
+    /// answered. See [Console::confirm]
+    pub fn confirm_blocking(&self, question: &str) -> Result<bool> {
+        crate::runtime::block_on(self.confirm(question))
+    }
+
+    /// Read a line, on the spawner when asked from the asynchronous loop
+    async fn read(&self, question: &str, masked: bool) -> Result<String> {
+        if let Some(held) = &self.held {
+            held.release()?;
+        }
+        let input = self.input.clone();
+        let read = move |question: &str| {
+            if masked {
+                input.read_masked_line(question)
+            } else {
+                input.read_line(question)
+            }
+        };
+        match &self.spawner {
+            Some(spawner) => {
+                let question = question.to_string();
+                Ok(spawner.run(move || read(&question)).await??)
+            }
+            None => Ok(read(question)?),
+        }
+    }
+}
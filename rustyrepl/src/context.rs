@@ -0,0 +1,331 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Nested command contexts (i.e. modal sub-REPLs), which a processor enters from one of its
+//! commands. Each context has its own command set, prompt, processor and history
+
+use anyhow::Result;
+use log::{info, warn};
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+
+use crate::commands::{AsyncReplCommandProcessor, ReplCommandProcessor};
+use crate::console::{Console, CONFIRMATION_QUESTION};
+use crate::parser::{parse_with_bypass, parses, CommandParser, ParseOutcome};
+
+/// The future of a context processing a line
+type ScopeFuture<'a> = Pin<Box<dyn Future<Output = Result<Processed>> + 'a>>;
+
+/// What processing a line within a context did, beyond succeeding or failing
+#[derive(Debug, Default)]
+pub(crate) enum Processed {
+    /// The line was processed, handled by `process_unparsed` or asked for help
+    #[default]
+    Done,
+    /// The line entered a nested context
+    Entered(ReplContext),
+    /// The line's command required confirmation, which was declined
+    Cancelled,
+    /// The line wasn't a valid command
+    Invalid,
+}
+
+/// A context's parser and processor with the command type erased, so contexts of differing
+/// command types can be stacked
+trait Scope {
+    /// Parse and process a line, returning what it did (i.e. the context it entered)
+    fn process(&mut self, tokens: &[&str], line: &str, console: &Console) -> Result<Processed>;
+
+    /// Parse and process a line asynchronously, resolving to what it did
+    fn process_async<'a>(
+        &'a mut self,
+        tokens: &'a [&'a str],
+        line: &'a str,
+        console: &'a Console,
+    ) -> ScopeFuture<'a>;
+
+    /// Whether the line parses as one of the context's commands (or its help)
+    fn parses(&mut self, tokens: &[&str]) -> bool;
+}
+
+/// A context around a synchronous [ReplCommandProcessor]
+struct SyncScope<C, P> {
+    parser: Box<dyn CommandParser<C>>,
+    processor: P,
+}
+
+impl<C, P> Scope for SyncScope<C, P>
+where
+    P: ReplCommandProcessor<C>,
+{
+    fn process(&mut self, tokens: &[&str], line: &str, console: &Console) -> Result<Processed> {
+        let (outcome, bypassed) = parse_with_bypass(&mut *self.parser, tokens);
+        match outcome {
+            ParseOutcome::Command(command) => {
+                if let Some(context) = self.processor.enter_context(&command) {
+                    return Ok(Processed::Entered(context));
+                }
+                let console = &console.clone().with_confirmation_bypassed(bypassed);
+                if self.processor.requires_confirmation(&command)
+                    && !console.confirm_blocking(CONFIRMATION_QUESTION)?
+                {
+                    info!("Command cancelled");
+                    return Ok(Processed::Cancelled);
+                }
+                let result = self.processor.process_command_result(command, console)?;
+                console.render_returned(result)?;
+            }
+            ParseOutcome::Help(help) => writeln!(console.out(), "{}", help)?,
+            ParseOutcome::Error(err) => {
+                if !self.processor.process_unparsed(line)? {
+                    warn!("Invalid command (type 'help' for the help menu\r\n{}", err);
+                    return Ok(Processed::Invalid);
+                }
+            }
+        }
+        Ok(Processed::Done)
+    }
+
+    fn process_async<'a>(
+        &'a mut self,
+        tokens: &'a [&'a str],
+        line: &'a str,
+        console: &'a Console,
+    ) -> ScopeFuture<'a> {
+        Box::pin(std::future::ready(self.process(tokens, line, console)))
+    }
+
+    fn parses(&mut self, tokens: &[&str]) -> bool {
+        parses(&mut *self.parser, tokens)
+    }
+}
+
+/// A context around an [AsyncReplCommandProcessor]
+struct AsyncScope<C, P> {
+    parser: Box<dyn CommandParser<C>>,
+    processor: P,
+}
+
+impl<C, P> Scope for AsyncScope<C, P>
+where
+    P: AsyncReplCommandProcessor<C>,
+{
+    fn process(&mut self, _tokens: &[&str], _line: &str, _console: &Console) -> Result<Processed> {
+        Err(anyhow::anyhow!(
+            "Asynchronous contexts can only be processed by Repl::process_async"
+        ))
+    }
+
+    fn process_async<'a>(
+        &'a mut self,
+        tokens: &'a [&'a str],
+        line: &'a str,
+        console: &'a Console,
+    ) -> ScopeFuture<'a> {
+        Box::pin(async move {
+            let (outcome, bypassed) = parse_with_bypass(&mut *self.parser, tokens);
+            match outcome {
+                ParseOutcome::Command(command) => {
+                    if let Some(context) = self.processor.enter_context(&command) {
+                        return Ok(Processed::Entered(context));
+                    }
+                    let console = &console.clone().with_confirmation_bypassed(bypassed);
+                    if self.processor.requires_confirmation(&command)
+                        && !console.confirm(CONFIRMATION_QUESTION).await?
+                    {
+                        info!("Command cancelled");
+                        return Ok(Processed::Cancelled);
+                    }
+                    let result = self
+                        .processor
+                        .process_command_result(command, console)
+                        .await?;
+                    console.render_returned(result)?;
+                }
+                ParseOutcome::Help(help) => writeln!(console.out(), "{}", help)?,
+                ParseOutcome::Error(err) => {
+                    if !self.processor.process_unparsed(line).await? {
+                        warn!("Invalid command (type 'help' for the help menu\r\n{}", err);
+                        return Ok(Processed::Invalid);
+                    }
+                }
+            }
+            Ok(Processed::Done)
+        })
+    }
+
+    fn parses(&mut self, tokens: &[&str]) -> bool {
+        parses(&mut *self.parser, tokens)
+    }
+}
+
+/// A nested command context, returned from a processor's `enter_context` for the command
+/// which enters it (i.e. `use db orders`). While in the context, lines are parsed against its
+/// own command set and handed to its own processor, with its own prompt and command history.
+/// `exit` leaves the context for the one it was entered from and `end` returns to the top level.
+///
+/// ```
+/// use anyhow::Result;
+/// use clap::Subcommand;
+/// use rustyrepl::{ClapParser, ReplCommandProcessor, ReplContext};
+///
+/// /// The commands available within a database
+/// #[derive(Subcommand, Debug)]
+/// enum TableCommand {
+///     /// Count the rows of the table
+///     Count,
+/// }
+///
+/// #[derive(Debug)]
+/// struct TableProcessor {
+///     table: String,
+/// }
+///
+/// impl ReplCommandProcessor<TableCommand> for TableProcessor {
+///     fn process_command(&self, command: TableCommand) -> Result<()> {
+///         match command {
+///             TableCommand::Count => println!("{} has 42 rows", self.table),
+///         }
+///         Ok(())
+///     }
+///
+///     fn is_quit(&self, command: &str) -> bool {
+///         matches!(command, "quit")
+///     }
+/// }
+///
+/// let context = ReplContext::new(
+///     "orders",
+///     ClapParser::<TableCommand>::from_subcommand(),
+///     TableProcessor { table: "orders".to_string() },
+/// );
+/// assert_eq!("orders> ", context.prompt());
+/// ```
+pub struct ReplContext {
+    /// The name of the context, which keys its command history
+    name: String,
+
+    /// The prompt displayed while in the context
+    prompt: String,
+
+    /// Whether the processor is asynchronous, requiring [crate::Repl::process_async]
+    asynchronous: bool,
+
+    /// The context's parser and processor
+    scope: Box<dyn Scope>,
+}
+
+impl std::fmt::Debug for ReplContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplContext")
+            .field("name", &self.name)
+            .field("prompt", &self.prompt)
+            .field("asynchronous", &self.asynchronous)
+            .finish()
+    }
+}
+
+impl ReplContext {
+    /// Construct a context around a synchronous processor, which can be entered from either
+    /// [crate::Repl::process] or [crate::Repl::process_async]. The prompt defaults to `<name>> `
+    ///
+    /// * `name` - The name of the context, which keys its command history for the session
+    /// * `parser` - The parser of each line within the context, i.e. a [crate::ClapParser]
+    /// * `processor` - The processor of the context's commands
+    pub fn new<C, T, P>(name: impl Into<String>, parser: T, processor: P) -> Self
+    where
+        C: 'static,
+        T: CommandParser<C> + 'static,
+        P: ReplCommandProcessor<C> + 'static,
+    {
+        Self::build(
+            name.into(),
+            false,
+            Box::new(SyncScope {
+                parser: Box::new(parser),
+                processor,
+            }),
+        )
+    }
+
+    /// Construct a context around an asynchronous processor, which can only be entered from
+    /// [crate::Repl::process_async]. The prompt defaults to `<name>> `
+    ///
+    /// * `name` - The name of the context, which keys its command history for the session
+    /// * `parser` - The parser of each line within the context, i.e. a [crate::ClapParser]
+    /// * `processor` - The processor of the context's commands
+    pub fn new_async<C, T, P>(name: impl Into<String>, parser: T, processor: P) -> Self
+    where
+        C: 'static,
+        T: CommandParser<C> + 'static,
+        P: AsyncReplCommandProcessor<C> + 'static,
+    {
+        Self::build(
+            name.into(),
+            true,
+            Box::new(AsyncScope {
+                parser: Box::new(parser),
+                processor,
+            }),
+        )
+    }
+
+    fn build(name: String, asynchronous: bool, scope: Box<dyn Scope>) -> Self {
+        Self {
+            prompt: format!("{}> ", name),
+            name,
+            asynchronous,
+            scope,
+        }
+    }
+
+    /// Display the supplied prompt while in the context
+    pub fn with_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// The name of the context
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The prompt displayed while in the context
+    pub fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    /// Whether the context's processor is asynchronous
+    pub(crate) fn is_async(&self) -> bool {
+        self.asynchronous
+    }
+
+    /// Parse and process a line within the context, returning what it did (i.e. the context it
+    /// entered)
+    pub(crate) fn process(
+        &mut self,
+        tokens: &[&str],
+        line: &str,
+        console: &Console,
+    ) -> Result<Processed> {
+        self.scope.process(tokens, line, console)
+    }
+
+    /// Parse and process a line within the context asynchronously, resolving to what it did
+    pub(crate) async fn process_async(
+        &mut self,
+        tokens: &[&str],
+        line: &str,
+        console: &Console,
+    ) -> Result<Processed> {
+        self.scope.process_async(tokens, line, console).await
+    }
+
+    /// Whether the line parses as one of the context's commands
+    pub(crate) fn parses(&mut self, tokens: &[&str]) -> bool {
+        self.scope.parses(tokens)
+    }
+}
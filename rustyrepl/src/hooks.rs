@@ -0,0 +1,83 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Hooks into the lifecycle of a REPL session, for cross-cutting behavior which shouldn't
+//! be repeated within every command's processing
+
+use anyhow::Result;
+use std::time::Duration;
+
+/// Hooks run by the REPL around the session and each of its top-level commands. Every hook
+/// does nothing by default, so only those needed are implemented.
+///
+/// ```
+/// use anyhow::Result;
+/// use rustyrepl::ReplHooks;
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// struct Banner;
+///
+/// impl<C> ReplHooks<C> for Banner {
+///     fn on_start(&self) -> Result<()> {
+///         println!("Welcome! Type 'help' for the available commands");
+///         Ok(())
+///     }
+///
+///     fn after_command(&self, result: &Result<()>, duration: Duration) {
+///         println!("{} in {:?}", if result.is_ok() { "Done" } else { "Failed" }, duration);
+///     }
+/// }
+/// ```
+pub trait ReplHooks<C>: std::fmt::Debug {
+    /// Run when the session starts, before the first prompt (i.e. to print a banner or
+    /// connect to a server). An error ends the session before it begins
+    fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Run before each parsed command is processed (i.e. for validation, authorization or
+    /// auditing). An error denies the command, which is reported and not processed
+    fn before_command(&self, command: &C) -> Result<()> {
+        let _ = command;
+        Ok(())
+    }
+
+    /// Run after each command is processed, with the result of processing it and how long
+    /// processing took
+    fn after_command(&self, result: &Result<()>, duration: Duration) {
+        let _ = (result, duration);
+    }
+
+    /// Run when the session ends, after the command history is saved (i.e. to clean up).
+    /// An error is returned from the REPL's processing
+    fn on_exit(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// No hooks, the REPL's default
+impl<C> ReplHooks<C> for () {}
+
+impl<C, T> ReplHooks<C> for Box<T>
+where
+    T: ReplHooks<C> + ?Sized,
+{
+    fn on_start(&self) -> Result<()> {
+        (**self).on_start()
+    }
+
+    fn before_command(&self, command: &C) -> Result<()> {
+        (**self).before_command(command)
+    }
+
+    fn after_command(&self, result: &Result<()>, duration: Duration) {
+        (**self).after_command(result, duration)
+    }
+
+    fn on_exit(&self) -> Result<()> {
+        (**self).on_exit()
+    }
+}
@@ -6,8 +6,8 @@
 //! rustyrepl is a simple read, evaluate, print, loop processor based on [clap] and utilizing
 //! [rustyline].
 //!
-//! You simply need to define your command structure in a [clap::Parser] derived
-//! struct and the processing logic, and the Repl will handle
+//! You simply need to define your commands in a [clap::Subcommand] derived enum (or a
+//! [clap::Parser] derived struct) and the processing logic, and the Repl will handle
 //!
 //! # Purpose
 //!
@@ -15,6 +15,7 @@
 //! 2. Storing and managing REPL history commands as well as an index of said commands for you
 //! 3. Allowing operators to get a help menu at any point, using the full Clap supported help interface (i.e. sub-command help as well)
 //! 4. Processing the commands as incoming
+//! 5. Printing asynchronous output (e.g. logs) above the prompt without corrupting the user's input, see [Repl::printer] and [ReplLogger]
 //!
 //! # Usage
 //!
@@ -29,7 +30,7 @@
 //!
 //! ```rust
 //! use anyhow::Result;
-//! use clap::{Parser, Subcommand};
+//! use clap::Subcommand;
 //! use rustyrepl::{Repl, ReplCommandProcessor};
 //!
 //! /// The enum of sub-commands supported by the CLI
@@ -39,24 +40,16 @@
 //!     Test,
 //! }
 //!
-//! /// The general CLI, essentially a wrapper for the sub-commands [Command]
-//! #[derive(Parser, Clone, Debug)]
-//! pub struct Cli {
-//!     #[clap(subcommand)]
-//!     command: Command,
-//! }
-//!
 //! #[derive(Debug)]
 //! pub struct CliProcessor {}
 //!
-//! #[async_trait::async_trait]
-//! impl ReplCommandProcessor<Cli> for CliProcessor {
+//! impl ReplCommandProcessor<Command> for CliProcessor {
 //!     fn is_quit(&self, command: &str) -> bool {
 //!         matches!(command, "quit" | "exit")
 //!     }
 //!
-//!     async fn process_command(&self, command: Cli) -> Result<()> {
-//!         match command.command {
+//!     fn process_command(&self, command: Command) -> Result<()> {
+//!         match command {
 //!             Command::Test => println!("A wild test appeared!"),
 //!         }
 //!         Ok(())
@@ -64,12 +57,11 @@
 //! }
 //!
 //! // MAIN //
-//! #[tokio::main]
-//! async fn main() -> Result<()> {
-//!     let processor: Box<dyn ReplCommandProcessor<Cli>> = Box::new(CliProcessor {});
+//! fn main() -> Result<()> {
+//!     let processor: Box<dyn ReplCommandProcessor<Command>> = Box::new(CliProcessor {});
 //!
-//!     let mut repl = Repl::<Cli>::new(processor, None, Some(">>".to_string()))?;
-//!     repl.process().await
+//!     let mut repl = Repl::<Command>::from_subcommand(processor, None, Some(">>".to_string()))?;
+//!     repl.process()
 //! }
 //! ```
 //!
@@ -77,9 +69,9 @@
 //!
 //! ```text
 //! >> help
-//! The general CLI, essentially a wrapper for the sub-commands [Commands]
+//! The enum of sub-commands supported by the CLI
 //!
-//! Usage: repl-interface <COMMAND>
+//! Usage: <COMMAND>
 //!
 //! Commands:
 //!   test  Execute a test command
@@ -89,12 +81,153 @@
 //!   -h, --help  Print help
 //!
 //! ```
+//!
+//! When the CLI needs top-level arguments alongside its sub-commands, derive [clap::Parser]
+//! on a wrapper structure and construct the REPL with [Repl::new] instead, with the processor
+//! receiving the full structure.
+//!
+//! Apps whose command set is only known at runtime (e.g. loaded from configuration or discovered
+//! plugins) can build a [clap::Command] with the builder API and construct the REPL with
+//! [Repl::from_command] instead. Processors then implement `ReplCommandProcessor<clap::ArgMatches>`
+//! and receive the matches for each line, with help and history behaving exactly as with a
+//! derived [clap::Parser].
+//!
+//! Parsing isn't limited to clap either. Implement [CommandParser] to turn each line's tokens
+//! into a command (or a help/error message) with argh, a hand-written DSL, an expression grammar,
+//! etc. and construct the REPL with [Repl::from_parser], keeping the REPL's loop and history
+//! handling. [ClapParser] is the default implementation, which can also be supplied this way to
+//! opt into resolving sub-commands from unique prefixes in any case (i.e. `dep` for `deploy`)
+//! with [ClapParser::with_prefix_matching].
+//!
+//! Lines which don't parse as a command are first offered to the processor's
+//! [ReplCommandProcessor::process_unparsed] hook, so bare text can be evaluated as an expression,
+//! run as a query or sent as a message. Only lines the hook declines (the default) display the
+//! parse error, along with a "did you mean" suggestion from the closest command (including aliases
+//! and built-in commands) or frequently used (and valid) history entry. With
+//! [Repl::with_suggestion_prompt] the user is asked whether to run the suggestion straight away.
+//!
+//! The REPL also has built-in meta-commands, prefixed with `:` so they never clash with your own.
+//! `:pin --env prod` pins global argument values for the rest of the session, injecting them into
+//! every later line which doesn't set them itself and showing them in the prompt. Arguments the
+//! parser doesn't accept ahead of every command are rejected. `:pin` lists the pinned values and
+//! `:unpin [--env]` removes them.
+//!
+//! Similarly `:default deploy --region us-east-1` defaults a sub-command's arguments whenever a
+//! line running it leaves them out, including lines naming it by an alias or prefix. `:default`
+//! lists the defaults and `:undefault [deploy [--region]]` clears them, while [Repl::with_saved_defaults] keeps them alongside the history file between
+//! sessions.
+//!
+//! Command hierarchies (IOS or Junos style) are built from nested [ReplContext]s. A processor's
+//! `enter_context` returns the context a command enters (i.e. `use db orders`), which has its own
+//! command set, prompt (`orders> `), processor and command history. `exit` leaves the context for
+//! the one it was entered from and `end` returns to the top level. Pinned and default arguments
+//! only apply to the top-level commands.
+//!
+//! Cross-cutting behavior (banners, connections, validation, auditing, timing, cleanup) doesn't
+//! need to be repeated in every command's processing. Implement the [ReplHooks] needed, which run
+//! at the start of the session, before and after each top-level command (receiving its result and
+//! duration) and at exit, and supply them with [Repl::with_hooks].
+//!
+//! For more control, [Repl::with_middleware] composes a (tower-like) chain of [ReplMiddleware]
+//! layers around the dispatch of each command to the processor. Each layer wraps the next, and may
+//! deny, transform, time, retry or log the command. [TimingMiddleware], [AuditMiddleware] and
+//! [ConfirmMiddleware] are ready-made, and layers can ask the user questions through the REPL's
+//! editor with [Next::console]. A layer denies a command by returning [StatusError::cancelled],
+//! which the REPL treats like a declined confirmation.
+//!
+//! Destructive commands are marked by returning true from the processor's
+//! `requires_confirmation`, and the user is asked `Are you sure? [y/N]` before they're processed.
+//! Adding `--yes` to the line skips the question. Scripts have nobody to ask, so they decline by
+//! default, which [Repl::with_script_confirmation] changes to accepting or reading the answer from
+//! the script's next line (see [ScriptConfirmation]).
+//!
+//! Commands which ask the user questions while processing implement the processor's
+//! `process_command_with`, which receives a [Console] for asking a question, a masked password,
+//! a yes or no confirmation or a choice between options. The questions are asked through the
+//! REPL's own editor and the answers kept out of the command history. Scripts supply the answers
+//! on the lines following the command. Synchronous processors ask with the blocking variants
+//! (i.e. [Console::ask_blocking]).
+//!
+//! The [Console] also has stdout-like and stderr-like streams, [Console::out] and [Console::err],
+//! which commands write their output to rather than printing it. The REPL writes its own output
+//! (i.e. help) there too. Both streams go to the terminal by default, and [Repl::with_output]
+//! captures them into an [OutputBuffer], tees them to a transcript or redirects them (see
+//! [ReplOutput]).
+//!
+//! Rather than formatting its own output, a command can return its result (anything
+//! `serde::Serialize`, or a custom [Render]) from the processor's `process_command_result`, or
+//! hand it to [Console::render]. The REPL renders it in the
+//! session's [OutputFormat]: a human readable table by default, or JSON, YAML or raw, switched
+//! with `:format json` (and shown with `:format`) or set up front with [Repl::with_format].
+//!
+//! A line's output can be redirected to a file (`list > tables.txt`, or `>> log.txt` to append)
+//! and piped through built-in filters: `| grep [-i] [-v] pattern`, `| head [n]`, `| tail [n]`
+//! and `| count`, i.e. `list | grep orders | head 20`. No shell is involved, the filters apply
+//! to what the command wrote to [Console::out]. Only standalone `|`, `>` and `>>` tokens
+//! following a valid command redirect, so lines handed to `process_unparsed` (i.e.
+//! `where a >= 5` or `a > b`) are passed on whole.
+//!
+//! With [Repl::with_pager], output taller than the terminal is paged rather than scrolling past
+//! the prompt, through `$PAGER` when it's set or otherwise a built-in pager (space for the next
+//! page, enter for the next line, `/` to search, `q` to quit). Scripts and output which isn't
+//! a terminal are never paged. Paged output is still copied to any transcript (see
+//! [ReplOutput::tee]), and output held back to page is written out before a command asks a
+//! question.
+//!
+//! Rendered results are kept by command number, so later lines can reference them as arguments:
+//! `$_` is the last result and `$_3` the result of command number 3, optionally followed by a
+//! path into the result (i.e. `inspect $_.id` or `drop $_3.0.name`). `:results` lists them.
+//!
+//! `$?` is the exit status of the last command (or meta-command), in arguments and the prompt: 0
+//! when it succeeded, the code of a [StatusError] it failed with, 1 for other failures (including
+//! denied or cancelled commands) and 2 for a line which wasn't a valid command or meta-command. By
+//! default a failed command ends the session, while the other failures only set `$?`. `:set -e`
+//! ends the session on any failure, `:set +e` continues past every failure, and `:set -x` echoes each line to the
+//! error stream before it's run. [Repl::status] reads the status once a script completes.
+//!
+//! `watch [-n secs] <command>` re-runs a command every 2 seconds (or every `secs`), redrawing
+//! its output (written through its [Console]) with what changed since the last run
+//! highlighted. Ctrl-C stops watching and returns to the prompt. Watched output can be filtered
+//! (i.e. `watch status | grep failed`), but commands can't be watched from scripts. An
+//! application's own `watch` command takes precedence over the built-in.
+//!
+//! For async processing, implement [AsyncReplCommandProcessor] instead (with a plain
+//! `async fn process_command`, no macros required) and drive the REPL with [Repl::process_async].
+//! Both processor kinds are always available. The returned futures aren't boxed nor required to be
+//! [Send], so processors may hold `!Send` state (e.g. `Rc`) on single-threaded executors.
+//!
+//! The async loop only relies on `std` futures, so any executor can drive it (tokio, async-std, smol,
+//! `futures::executor`, ...). Blocking terminal reads run on a dedicated thread by default, or on a
+//! runtime's own blocking pool via `Repl::with_blocking_spawner`. See the `examples/` directory for each runtime.
 
 mod commands;
+mod console;
+mod context;
+mod hooks;
+mod middleware;
+mod output;
+mod parser;
+mod printer;
+mod render;
 mod repl;
+mod runtime;
+mod status;
 
 #[cfg(test)]
 pub(crate) mod common_test;
 
+pub use crate::commands::AsyncReplCommandProcessor;
 pub use crate::commands::ReplCommandProcessor;
+pub use crate::console::{Console, ScriptConfirmation};
+pub use crate::context::ReplContext;
+pub use crate::hooks::ReplHooks;
+pub use crate::middleware::{
+    AuditMiddleware, BoxFuture, ConfirmMiddleware, Next, ReplMiddleware, TimingMiddleware,
+};
+pub use crate::output::{OutputBuffer, OutputStream, ReplOutput};
+pub use crate::parser::{ClapParser, CommandParser, ParseOutcome};
+pub use crate::printer::{ReplLogger, ReplPrinter};
+pub use crate::render::{OutputFormat, Render};
 pub use crate::repl::Repl;
+pub use crate::runtime::{BlockingJob, BlockingSpawner, ThreadSpawner};
+pub use crate::status::StatusError;
@@ -0,0 +1,202 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! A composable (tower-like) middleware chain around the dispatch of each top-level command
+//! to the processor, with ready-made layers for timing, audit logging and confirmation
+
+use anyhow::Result;
+use log::{info, log, Level};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+use crate::console::{Console, CONFIRMATION_QUESTION};
+use crate::status::StatusError;
+
+/// A boxed future, which isn't required to be [Send] so `!Send` processors can be wrapped
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A layer wrapping the dispatch of each top-level command. Each layer receives the parsed
+/// command along with the rest of the chain, and may deny the command (by returning
+/// [crate::StatusError::cancelled] rather than running the rest of the chain), transform it,
+/// measure it, retry it or log it. Layers are added with [crate::Repl::with_middleware], with
+/// the first added being the outermost.
+///
+/// ```
+/// use anyhow::Result;
+/// use rustyrepl::{BoxFuture, Next, ReplMiddleware, StatusError};
+///
+/// /// Denies every command during maintenance, and otherwise retries each command once if
+/// /// it fails
+/// #[derive(Debug)]
+/// struct Maintenance {
+///     enabled: bool,
+/// }
+///
+/// impl<C: Clone + 'static> ReplMiddleware<C> for Maintenance {
+///     fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>> {
+///         Box::pin(async move {
+///             if self.enabled {
+///                 return Err(StatusError::cancelled().into());
+///             }
+///             match next.run(command.clone()).await {
+///                 Ok(()) => Ok(()),
+///                 Err(_) => next.run(command).await,
+///             }
+///         })
+///     }
+/// }
+/// ```
+pub trait ReplMiddleware<C>: std::fmt::Debug {
+    /// Handle the command, typically running the rest of the chain with [Next::run]
+    fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>>;
+}
+
+impl<C, T> ReplMiddleware<C> for Box<T>
+where
+    T: ReplMiddleware<C> + ?Sized,
+{
+    fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>> {
+        (**self).call(command, next)
+    }
+}
+
+/// The dispatch of a command to the processor, at the end of the chain
+pub(crate) type Dispatch<'a, C> = dyn Fn(C) -> BoxFuture<'a, Result<()>> + 'a;
+
+/// The remainder of the middleware chain, ending with the processor. It's [Copy], so a
+/// layer can run it more than once (i.e. to retry)
+pub struct Next<'a, C> {
+    layers: &'a [Box<dyn ReplMiddleware<C>>],
+    dispatch: &'a Dispatch<'a, C>,
+    console: &'a Console,
+}
+
+impl<C> Clone for Next<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Next<'_, C> {}
+
+impl<C> std::fmt::Debug for Next<'_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next")
+            .field("layers", &self.layers)
+            .finish()
+    }
+}
+
+impl<'a, C> Next<'a, C> {
+    pub(crate) fn new(
+        layers: &'a [Box<dyn ReplMiddleware<C>>],
+        dispatch: &'a Dispatch<'a, C>,
+        console: &'a Console,
+    ) -> Self {
+        Self {
+            layers,
+            dispatch,
+            console,
+        }
+    }
+
+    /// Run the rest of the chain with the command
+    pub fn run(self, command: C) -> BoxFuture<'a, Result<()>> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.call(command, Self { layers, ..self }),
+            None => (self.dispatch)(command),
+        }
+    }
+
+    /// A handle to ask the user questions through the REPL's editor
+    pub fn console(&self) -> &'a Console {
+        self.console
+    }
+}
+
+/// Logs how long each command took to process
+#[derive(Debug, Clone, Copy)]
+pub struct TimingMiddleware {
+    level: Level,
+}
+
+impl Default for TimingMiddleware {
+    fn default() -> Self {
+        Self { level: Level::Info }
+    }
+}
+
+impl TimingMiddleware {
+    /// Log the timing at the supplied level, rather than [Level::Info]
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl<C: 'static> ReplMiddleware<C> for TimingMiddleware {
+    fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = next.run(command).await;
+            log!(self.level, "Command completed in {:?}", started.elapsed());
+            result
+        })
+    }
+}
+
+/// Logs each command, and whether it succeeded, to the `rustyrepl::audit` log target
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AuditMiddleware;
+
+impl<C: std::fmt::Debug + 'static> ReplMiddleware<C> for AuditMiddleware {
+    fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let description = format!("{:?}", command);
+            let result = next.run(command).await;
+            match &result {
+                Ok(()) => info!(target: "rustyrepl::audit", "{} succeeded", description),
+                Err(err) => info!(target: "rustyrepl::audit", "{} failed: {}", description, err),
+            }
+            result
+        })
+    }
+}
+
+/// Asks the user to confirm commands matching a predicate before they're processed.
+/// Declined commands are cancelled (see [crate::StatusError::cancelled]), lines run with `--yes` aren't asked, and scripts answer
+/// according to the REPL's [crate::ScriptConfirmation] policy
+pub struct ConfirmMiddleware<C> {
+    requires_confirmation: Box<dyn Fn(&C) -> bool>,
+}
+
+impl<C> std::fmt::Debug for ConfirmMiddleware<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ConfirmMiddleware")
+    }
+}
+
+impl<C> ConfirmMiddleware<C> {
+    /// Confirm every command for which the predicate returns true
+    pub fn new<F: Fn(&C) -> bool + 'static>(requires_confirmation: F) -> Self {
+        Self {
+            requires_confirmation: Box::new(requires_confirmation),
+        }
+    }
+}
+
+impl<C: 'static> ReplMiddleware<C> for ConfirmMiddleware<C> {
+    fn call<'a>(&'a self, command: C, next: Next<'a, C>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if (self.requires_confirmation)(&command)
+                && !next.console().confirm(CONFIRMATION_QUESTION).await?
+            {
+                return Err(StatusError::cancelled().into());
+            }
+            next.run(command).await
+        })
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Where commands (and the REPL itself) write their output, so it can be captured, teed to a
+//! transcript or redirected rather than always going straight to the terminal
+
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A writer which may be shared between streams (i.e. a transcript receiving both stdout and
+/// stderr)
+#[derive(Clone)]
+struct SharedWriter(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl SharedWriter {
+    fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(writer))))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Box<dyn Write + Send>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A cloneable handle to one of the REPL's output streams, which writes everything to each of
+/// the stream's targets. Retrieve one from [crate::Console::out] or [crate::Console::err]
+#[derive(Clone)]
+pub struct OutputStream {
+    targets: Vec<SharedWriter>,
+}
+
+impl std::fmt::Debug for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputStream")
+            .field("targets", &self.targets.len())
+            .finish()
+    }
+}
+
+impl OutputStream {
+    fn new<W: Write + Send + 'static>(target: W) -> Self {
+        Self {
+            targets: vec![SharedWriter::new(target)],
+        }
+    }
+
+    /// The stream with its primary target (i.e. stdout) replaced, keeping any teed targets
+    fn with_primary<W: Write + Send + 'static>(&self, primary: W) -> Self {
+        let mut targets = self.targets.clone();
+        targets[0] = SharedWriter::new(primary);
+        Self { targets }
+    }
+
+    /// The stream, additionally writing to the target
+    fn with_target(&self, target: SharedWriter) -> Self {
+        let mut targets = self.targets.clone();
+        targets.push(target);
+        Self { targets }
+    }
+
+    /// The stream without its primary target (i.e. stdout), so only writing to the targets
+    /// teed with [ReplOutput::tee]
+    pub(crate) fn copies(&self) -> Self {
+        Self {
+            targets: self.targets.iter().skip(1).cloned().collect(),
+        }
+    }
+}
+
+impl Write for OutputStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for target in &self.targets {
+            target.lock().write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for target in &self.targets {
+            target.lock().flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// A cloneable in-memory buffer to capture output into, i.e. to test what commands print
+#[derive(Debug, Default, Clone)]
+pub struct OutputBuffer {
+    contents: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.contents
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Everything written to the buffer so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.lock()).into_owned()
+    }
+
+    /// Everything written to the buffer so far, emptying it
+    pub fn take(&self) -> String {
+        let contents = std::mem::take(&mut *self.lock());
+        String::from_utf8_lossy(&contents).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Output held back until the line completes (i.e. to page it), which is written out early
+/// when the user is asked a question so it's shown before the question
+#[derive(Debug, Clone)]
+pub(crate) struct HeldOutput {
+    held: OutputBuffer,
+    out: OutputStream,
+}
+
+impl HeldOutput {
+    pub(crate) fn new(held: OutputBuffer, out: OutputStream) -> Self {
+        Self { held, out }
+    }
+
+    /// Write out everything held so far, while continuing to hold what's written next
+    pub(crate) fn release(&self) -> std::io::Result<()> {
+        let held = self.held.take();
+        let mut out = self.out.clone();
+        out.write_all(held.as_bytes())?;
+        out.flush()
+    }
+}
+
+/// Where the REPL's output is written, with a stdout-like stream for command output and a
+/// stderr-like stream for diagnostics. Both go to the terminal by default, and are set with
+/// [crate::Repl::with_output].
+///
+/// ```
+/// use rustyrepl::{OutputBuffer, ReplOutput};
+/// use std::io::Write;
+///
+/// // capture command output, while keeping a transcript of everything written
+/// let captured = OutputBuffer::default();
+/// let transcript = OutputBuffer::default();
+/// let output = ReplOutput::default()
+///     .redirect(captured.clone())
+///     .tee(transcript.clone());
+///
+/// writeln!(output.out(), "3 rows").unwrap();
+/// writeln!(output.err(), "1 warning").unwrap();
+/// assert_eq!("3 rows\n", captured.contents());
+/// assert_eq!("3 rows\n1 warning\n", transcript.contents());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplOutput {
+    out: OutputStream,
+    err: OutputStream,
+    /// Whether command output still goes to stdout
+    terminal: bool,
+}
+
+impl Default for ReplOutput {
+    fn default() -> Self {
+        Self {
+            out: OutputStream::new(std::io::stdout()),
+            err: OutputStream::new(std::io::stderr()),
+            terminal: true,
+        }
+    }
+}
+
+impl ReplOutput {
+    /// Write command output to the supplied writer rather than stdout. Writers already added
+    /// with [ReplOutput::tee] keep receiving it
+    pub fn redirect<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.out = self.out.with_primary(writer);
+        self.terminal = false;
+        self
+    }
+
+    /// Write diagnostics to the supplied writer rather than stderr. Writers already added with
+    /// [ReplOutput::tee] keep receiving them
+    pub fn redirect_err<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.err = self.err.with_primary(writer);
+        self
+    }
+
+    /// Write command output only to the supplied writer, without any teed copies (i.e. to
+    /// capture it before it's paged or filtered)
+    pub(crate) fn capture<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.out = OutputStream::new(writer);
+        self.terminal = false;
+        self
+    }
+
+    /// Write diagnostics only to the supplied writer, without any teed copies
+    pub(crate) fn capture_err<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.err = OutputStream::new(writer);
+        self
+    }
+
+    /// Copy both command output and diagnostics to the supplied writer (i.e. a transcript),
+    /// in addition to wherever they're already written
+    pub fn tee<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        let shared = SharedWriter::new(writer);
+        self.out = self.out.with_target(shared.clone());
+        self.err = self.err.with_target(shared);
+        self
+    }
+
+    /// The stdout-like stream, for command output
+    pub fn out(&self) -> OutputStream {
+        self.out.clone()
+    }
+
+    /// The stderr-like stream, for diagnostics
+    pub fn err(&self) -> OutputStream {
+        self.err.clone()
+    }
+
+    /// Whether command output is written to stdout, rather than redirected
+    pub(crate) fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+}
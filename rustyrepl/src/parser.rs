@@ -0,0 +1,437 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Parsing of a user's tokenized input line into a command for the processor. [clap] is the
+//! default, however any parser (argh, a hand-written DSL, an expression grammar, ...) can be
+//! plugged in by implementing [CommandParser]
+
+use std::marker::PhantomData;
+
+/// The name of the command built around a [clap::Subcommand] enum
+const SUBCOMMAND_REPL_NAME: &str = "repl";
+
+/// The flag which skips confirming a destructive command
+pub(crate) const CONFIRMATION_BYPASS_FLAG: &str = "--yes";
+
+/// The result of parsing a single line of input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseOutcome<C> {
+    /// A command which is handed to the processor
+    Command(C),
+    /// Informational output, such as a help menu or version, which is displayed to the user
+    Help(String),
+    /// The line isn't a valid command, with the message describing why
+    Error(String),
+}
+
+/// Parses the tokens of a line of input into a command of type `C`.
+///
+/// ```
+/// use rustyrepl::{CommandParser, ParseOutcome};
+///
+/// /// Parses "add <a> <b>" into the pair of numbers to sum
+/// #[derive(Debug)]
+/// struct AddParser;
+///
+/// impl CommandParser<(i64, i64)> for AddParser {
+///     fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<(i64, i64)> {
+///         match tokens {
+///             ["help"] => ParseOutcome::Help("add <a> <b>".to_string()),
+///             ["add", a, b] => match (a.parse(), b.parse()) {
+///                 (Ok(a), Ok(b)) => ParseOutcome::Command((a, b)),
+///                 _ => ParseOutcome::Error("Arguments must be integers".to_string()),
+///             },
+///             _ => ParseOutcome::Error(format!("Unknown command {:?}", tokens)),
+///         }
+///     }
+/// }
+///
+/// assert_eq!(ParseOutcome::Command((1, 2)), AddParser.parse(&["add", "1", "2"]));
+/// ```
+pub trait CommandParser<C>: std::fmt::Debug {
+    /// Parse the whitespace separated tokens of a line into a command, or the help
+    /// or error message to display instead
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<C>;
+
+    /// The names (including aliases) of the top-level commands, which the REPL suggests
+    /// as corrections for mistyped input. None are suggested by default
+    fn command_names(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The tokens with each sub-command named by an alias (or however else the parser
+    /// accepts it, i.e. a prefix) replaced by its name, which the REPL uses to find the
+    /// line's `:default`s. Tokens are returned unchanged by default
+    fn resolve(&mut self, tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|token| token.to_string()).collect()
+    }
+}
+
+/// The default [CommandParser], which parses lines against a [clap::Command] definition.
+///
+/// Lines are parsed without a binary name in front of them, so usage and help output reads
+/// like REPL syntax (i.e. `Usage: test [OPTIONS]`). Use [ClapParser::with_app_name] to
+/// include the application's name instead.
+///
+/// Sub-command names must be typed exactly unless [ClapParser::with_prefix_matching] is enabled
+pub struct ClapParser<C> {
+    /// The clap command definition each line is parsed against
+    command: clap::Command,
+
+    /// Whether the application's name leads usage output
+    app_name: bool,
+
+    /// Whether sub-commands resolve from unique prefixes and regardless of case
+    prefix_matching: bool,
+
+    /// The command with its usage naming applied, prepared on first use
+    prepared: Option<clap::Command>,
+
+    /// Conversion of the parsed [clap::ArgMatches] into the command structure
+    from_matches: fn(&mut clap::ArgMatches) -> Result<C, clap::Error>,
+
+    /// Phantom holder for the command structure
+    _command_type: PhantomData<C>,
+}
+
+impl<C> std::fmt::Debug for ClapParser<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClapParser")
+            .field("command", &self.command)
+            .finish()
+    }
+}
+
+impl<C> ClapParser<C> {
+    fn build(
+        command: clap::Command,
+        from_matches: fn(&mut clap::ArgMatches) -> Result<C, clap::Error>,
+    ) -> Self {
+        Self {
+            command: command.no_binary_name(true),
+            app_name: false,
+            prefix_matching: false,
+            prepared: None,
+            from_matches,
+            _command_type: PhantomData,
+        }
+    }
+
+    /// Set the binary names which lead usage output. Without a binary name clap names
+    /// sub-commands on their own, so only the top-level usage needs to be rewritten to drop
+    /// the command's name
+    fn set_bin_names(command: clap::Command, app_name: bool) -> clap::Command {
+        if app_name {
+            let name = command.get_name().to_string();
+            return command.bin_name(name);
+        }
+        let usage = command.clone().bin_name("").render_usage().to_string();
+        let usage = usage.trim_start_matches("Usage:").trim().to_string();
+        command.override_usage(usage)
+    }
+
+    /// Lead usage output with the application's name (i.e. [clap::Command::get_name], which
+    /// for a [clap::Parser] defaults to the crate name) rather than just the REPL syntax
+    pub fn with_app_name(mut self) -> Self {
+        self.app_name = true;
+        self.prepared = None;
+        self
+    }
+
+    /// Resolve sub-commands from any unambiguous prefix of their name or aliases (i.e. `dep`
+    /// for `deploy`), ignoring case. An ambiguous prefix is reported with the candidates
+    /// it could refer to, rather than being handed to clap
+    pub fn with_prefix_matching(mut self) -> Self {
+        self.prefix_matching = true;
+        self
+    }
+
+    /// The underlying clap command definition
+    pub fn command(&self) -> &clap::Command {
+        &self.command
+    }
+
+    /// The command with its usage naming applied, built so that generated sub-commands
+    /// (i.e. `help`) and global arguments are in place
+    fn prepared(&mut self) -> &mut clap::Command {
+        let (command, app_name) = (&self.command, self.app_name);
+        self.prepared.get_or_insert_with(|| {
+            let mut prepared = Self::set_bin_names(command.clone(), app_name);
+            prepared.build();
+            prepared
+        })
+    }
+
+    /// Parse the tokens of a line into the command, or clap's error
+    fn try_parse(&mut self, tokens: &[&str]) -> Result<C, clap::Error> {
+        let from_matches = self.from_matches;
+        let prepared = self.prepared();
+        let mut matches = prepared.try_get_matches_from_mut(tokens.iter().copied())?;
+        from_matches(&mut matches).map_err(|err| err.format(prepared))
+    }
+
+    /// Replace each token naming a sub-command by an alias or, when `prefixes` is set, by a
+    /// unique prefix or in a different case, with the sub-command's full name. Tokens are
+    /// walked the way clap parses them, so option values, arguments following `--` and the
+    /// values of required positional arguments (which precede any sub-command) are never
+    /// rewritten
+    fn resolve_subcommands(
+        &mut self,
+        tokens: &[&str],
+        prefixes: bool,
+    ) -> Result<Vec<String>, String> {
+        let mut current: &clap::Command = self.prepared();
+        let mut positionals = 0;
+        let mut resolved = Vec::with_capacity(tokens.len());
+        let mut iter = tokens.iter();
+        while let Some(token) = iter.next() {
+            resolved.push(token.to_string());
+            if *token == "--" {
+                resolved.extend(iter.map(|token| token.to_string()));
+                break;
+            }
+            if token.starts_with('-') && token.len() > 1 {
+                if Self::option_takes_value(current, token) {
+                    resolved.extend(iter.next().map(|token| token.to_string()));
+                }
+                continue;
+            }
+            let required = current
+                .get_positionals()
+                .filter(|arg| arg.is_required_set())
+                .count();
+            if positionals < required {
+                positionals += 1;
+                continue;
+            }
+            match Self::match_subcommand(current, token, prefixes)? {
+                Some(subcommand) => {
+                    if let Some(last) = resolved.last_mut() {
+                        *last = subcommand.get_name().to_string();
+                    }
+                    // `help` takes the names of its parent's sub-commands, so keep resolving those
+                    if subcommand.get_name() != "help" {
+                        current = subcommand;
+                        positionals = 0;
+                    }
+                }
+                None => positionals += 1,
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Whether the option token (i.e. `--region`, `-r`) consumes the following token as its value
+    fn option_takes_value(command: &clap::Command, token: &str) -> bool {
+        let arg = match token.strip_prefix("--") {
+            Some(long) if !long.contains('=') => command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long)),
+            Some(_) => None,
+            None => {
+                // a short option only takes the next token if it's the last in a cluster
+                let mut shorts = token[1..].chars();
+                match (shorts.next(), shorts.next()) {
+                    (Some(short), None) => command
+                        .get_arguments()
+                        .find(|arg| arg.get_short() == Some(short)),
+                    _ => None,
+                }
+            }
+        };
+        arg.map(|arg| arg.get_action().takes_values())
+            .unwrap_or(false)
+    }
+
+    /// Find the sub-command of `command` named by the token or, when `prefixes` is set,
+    /// uniquely prefixed by it ignoring case
+    fn match_subcommand<'a>(
+        command: &'a clap::Command,
+        token: &str,
+        prefixes: bool,
+    ) -> Result<Option<&'a clap::Command>, String> {
+        let names = |subcommand: &'a clap::Command| {
+            std::iter::once(subcommand.get_name()).chain(subcommand.get_all_aliases())
+        };
+        if !prefixes {
+            return Ok(command
+                .get_subcommands()
+                .find(|subcommand| names(subcommand).any(|name| name == token)));
+        }
+        let lowercase = token.to_lowercase();
+
+        if let Some(exact) = command
+            .get_subcommands()
+            .find(|subcommand| names(subcommand).any(|name| name.to_lowercase() == lowercase))
+        {
+            return Ok(Some(exact));
+        }
+        let candidates: Vec<&clap::Command> = command
+            .get_subcommands()
+            .filter(|subcommand| {
+                names(subcommand).any(|name| name.to_lowercase().starts_with(&lowercase))
+            })
+            .collect();
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [unique] => Ok(Some(*unique)),
+            ambiguous => Err(format!(
+                "error: '{}' is ambiguous, it could be any of: {}",
+                token,
+                ambiguous
+                    .iter()
+                    .map(|subcommand| subcommand.get_name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+impl<C> ClapParser<C>
+where
+    C: clap::Parser,
+{
+    /// Parse lines into a [clap::Parser] structure
+    pub fn new() -> Self {
+        Self::build(C::command(), C::from_arg_matches_mut)
+    }
+}
+
+impl<C> Default for ClapParser<C>
+where
+    C: clap::Parser,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> ClapParser<C>
+where
+    C: clap::Subcommand,
+{
+    /// Parse lines directly into a [clap::Subcommand] enum, where each variant is a
+    /// top-level command. The resulting command is named "repl"
+    pub fn from_subcommand() -> Self {
+        let command = C::augment_subcommands(
+            clap::Command::new(SUBCOMMAND_REPL_NAME).subcommand_required(true),
+        );
+        Self::build(command, C::from_arg_matches_mut)
+    }
+}
+
+impl ClapParser<clap::ArgMatches> {
+    /// Parse lines against a [clap::Command] built at runtime, producing the [clap::ArgMatches]
+    pub fn from_command(command: clap::Command) -> Self {
+        Self::build(command, |matches| Ok(matches.clone()))
+    }
+}
+
+impl<C> CommandParser<C> for ClapParser<C> {
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<C> {
+        let resolved;
+        let tokens = if self.prefix_matching {
+            resolved = match self.resolve_subcommands(tokens, true) {
+                Ok(resolved) => resolved,
+                Err(ambiguous) => return ParseOutcome::Error(ambiguous),
+            };
+            resolved.iter().map(String::as_str).collect::<Vec<_>>()
+        } else {
+            tokens.to_vec()
+        };
+        match self.try_parse(&tokens) {
+            Ok(command) => ParseOutcome::Command(command),
+            Err(clap_err) => match clap_err.kind() {
+                clap::error::ErrorKind::DisplayHelp
+                | clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+                | clap::error::ErrorKind::DisplayVersion => {
+                    ParseOutcome::Help(clap_err.to_string())
+                }
+                _ => ParseOutcome::Error(clap_err.to_string()),
+            },
+        }
+    }
+
+    fn command_names(&mut self) -> Vec<String> {
+        self.prepared()
+            .get_subcommands()
+            .flat_map(|subcommand| {
+                std::iter::once(subcommand.get_name()).chain(subcommand.get_all_aliases())
+            })
+            .map(String::from)
+            .collect()
+    }
+
+    fn resolve(&mut self, tokens: &[&str]) -> Vec<String> {
+        let prefixes = self.prefix_matching;
+        self.resolve_subcommands(tokens, prefixes)
+            .unwrap_or_else(|_| tokens.iter().map(|token| token.to_string()).collect())
+    }
+}
+
+/// Parse the tokens, consuming the [CONFIRMATION_BYPASS_FLAG] if the line is only valid without
+/// it, so commands which define their own flag of the same name still receive it. Returns the
+/// outcome, and whether confirmation was bypassed
+pub(crate) fn parse_with_bypass<C>(
+    parser: &mut dyn CommandParser<C>,
+    tokens: &[&str],
+) -> (ParseOutcome<C>, bool) {
+    let outcome = parser.parse(tokens);
+    if matches!(outcome, ParseOutcome::Error(_)) && tokens.contains(&CONFIRMATION_BYPASS_FLAG) {
+        let stripped: Vec<&str> = tokens
+            .iter()
+            .copied()
+            .filter(|token| *token != CONFIRMATION_BYPASS_FLAG)
+            .collect();
+        if let ParseOutcome::Command(command) = parser.parse(&stripped) {
+            return (ParseOutcome::Command(command), true);
+        }
+    }
+    (outcome, false)
+}
+
+/// Whether the tokens parse as a command (or its help), rather than a line for the processor's
+/// `process_unparsed`
+pub(crate) fn parses<C>(parser: &mut dyn CommandParser<C>, tokens: &[&str]) -> bool {
+    !matches!(parse_with_bypass(parser, tokens).0, ParseOutcome::Error(_))
+}
+
+/// Check the options apply to every line (i.e. they're the parser's global arguments), by
+/// parsing them ahead of `--help`. Parsers which don't display help for `--help` can't be
+/// checked, so their options are accepted
+pub(crate) fn check_global_options<C>(
+    parser: &mut dyn CommandParser<C>,
+    options: &[&str],
+) -> Result<(), String> {
+    if !matches!(parser.parse(&["--help"]), ParseOutcome::Help(_)) {
+        return Ok(());
+    }
+    let probe: Vec<&str> = options.iter().copied().chain(["--help"]).collect();
+    match parser.parse(&probe) {
+        ParseOutcome::Error(err) => {
+            let reason = err.lines().next().unwrap_or_default();
+            Err(reason.trim_start_matches("error: ").to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+impl<C, T> CommandParser<C> for Box<T>
+where
+    T: CommandParser<C> + ?Sized,
+{
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<C> {
+        (**self).parse(tokens)
+    }
+
+    fn command_names(&mut self) -> Vec<String> {
+        (**self).command_names()
+    }
+
+    fn resolve(&mut self, tokens: &[&str]) -> Vec<String> {
+        (**self).resolve(tokens)
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Printing of asynchronous output (log lines, background tasks, etc) above the
+//! REPL prompt without corrupting what the user is currently typing
+
+use anyhow::Result;
+use log::{Level, LevelFilter, Metadata, Record};
+use rustyline::ExternalPrinter;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// A cloneable, [Send]able handle which writes messages above the REPL's prompt.
+///
+/// While the REPL is waiting on user input, the message is printed and then the
+/// prompt along with any partially typed input is redrawn underneath it. When the
+/// terminal doesn't support this (e.g. input or output isn't a TTY), messages are
+/// written straight to stdout.
+///
+/// Retrieve one with [crate::Repl::printer]
+#[derive(Clone)]
+pub struct ReplPrinter {
+    external: Option<Arc<Mutex<Box<dyn ExternalPrinter + Send>>>>,
+}
+
+impl std::fmt::Debug for ReplPrinter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplPrinter")
+            .field("external", &self.external.is_some())
+            .finish()
+    }
+}
+
+impl ReplPrinter {
+    /// Construct a printer around rustyline's external printer. If none is
+    /// available, output falls back to stdout
+    pub(crate) fn new(external: Option<Box<dyn ExternalPrinter + Send>>) -> Self {
+        Self {
+            external: external.map(|printer| Arc::new(Mutex::new(printer))),
+        }
+    }
+
+    /// Print the supplied message above the prompt. A trailing newline is
+    /// added if the message doesn't already end with one
+    pub fn print<S: Into<String>>(&self, message: S) -> Result<()> {
+        let mut message = message.into();
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+
+        if let Some(external) = &self.external {
+            let mut printer = external
+                .lock()
+                .map_err(|_| anyhow::anyhow!("REPL printer lock poisoned"))?;
+            printer.print(message)?;
+        } else {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(message.as_bytes())?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// A [log::Log] implementation which routes every record through a [ReplPrinter],
+/// so logging from any thread doesn't clobber the REPL's input line.
+///
+/// ```no_run
+/// # use anyhow::Result;
+/// # use clap::Parser;
+/// # use rustyrepl::{Repl, ReplLogger};
+/// # #[derive(Parser, Debug)]
+/// # struct Cli {}
+/// # fn build() -> Result<Repl<Cli>> { unimplemented!() }
+/// let mut repl: Repl<Cli> = build()?;
+/// ReplLogger::new(repl.printer(), log::Level::Info).init()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct ReplLogger {
+    printer: ReplPrinter,
+    level: Level,
+    formatter: fn(&Record) -> String,
+}
+
+impl std::fmt::Debug for ReplLogger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplLogger")
+            .field("printer", &self.printer)
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+impl ReplLogger {
+    /// Construct a new logger which prints records at or above the given level
+    pub fn new(printer: ReplPrinter, level: Level) -> Self {
+        Self {
+            printer,
+            level,
+            formatter: Self::default_format,
+        }
+    }
+
+    /// Override how a log record is formatted into a line of output
+    pub fn with_formatter(mut self, formatter: fn(&Record) -> String) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    /// Install this logger as the global logger for the [log] crate
+    pub fn init(self) -> Result<()> {
+        let filter: LevelFilter = self.level.to_level_filter();
+        log::set_boxed_logger(Box::new(self))
+            .map(|()| log::set_max_level(filter))
+            .map_err(|err| anyhow::anyhow!("Failed to set the REPL logger: {}", err))
+    }
+
+    fn default_format(record: &Record) -> String {
+        format!("{:6} {}", record.level(), record.args())
+    }
+}
+
+impl log::Log for ReplLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = self.printer.print((self.formatter)(record));
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
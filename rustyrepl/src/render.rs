@@ -0,0 +1,194 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Rendering of typed command results in the session's output format, so commands can serve
+//! both humans (tables) and scripts (JSON, YAML) without formatting their own output
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The format command results are rendered in, switched during a session with `:format`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A human readable table, with a column per field (the default)
+    #[default]
+    Table,
+    /// Pretty-printed JSON
+    Json,
+    /// YAML
+    Yaml,
+    /// Strings and numbers as they are, anything else as compact JSON
+    Raw,
+}
+
+impl OutputFormat {
+    /// The names of every format, as accepted by `:format`
+    pub(crate) const NAMES: &'static [&'static str] = &["table", "json", "yaml", "raw"];
+
+    /// Render a serializable value in the format
+    pub fn render_serialized<T: Serialize + ?Sized>(self, value: &T) -> Result<String> {
+        let value = serde_json::to_value(value)?;
+        Ok(match self {
+            OutputFormat::Table => table(&value),
+            OutputFormat::Json => serde_json::to_string_pretty(&value)?,
+            OutputFormat::Yaml => serde_yaml::to_string(&value)?.trim_end().to_string(),
+            OutputFormat::Raw => match &value {
+                Value::Array(values) => values.iter().map(cell).collect::<Vec<_>>().join("\n"),
+                value => cell(value),
+            },
+        })
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Raw => "raw",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "raw" => Ok(OutputFormat::Raw),
+            _ => bail!(
+                "Unknown format '{}', expected one of: {}",
+                name,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// A command result which the REPL renders in the session's [OutputFormat], returned from
+/// [crate::ReplCommandProcessor::process_command_result] or handed over with
+/// [crate::Console::render]. Every [Serialize] type is rendered from its serialized form, and
+/// other types can implement their own rendering.
+///
+/// ```
+/// use rustyrepl::OutputFormat;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Table {
+///     name: String,
+///     rows: usize,
+/// }
+///
+/// let tables = vec![
+///     Table { name: "orders".to_string(), rows: 42 },
+///     Table { name: "customers".to_string(), rows: 7 },
+/// ];
+/// assert_eq!(
+///     "name       rows\n---------  ----\norders     42\ncustomers  7",
+///     OutputFormat::Table.render_serialized(&tables).unwrap()
+/// );
+/// ```
+pub trait Render {
+    /// Render the result in the supplied format
+    fn render(&self, format: OutputFormat) -> Result<String>;
+
+    /// The result as structured data, kept for later commands to reference with `$_`. The
+    /// raw rendering by default
+    fn to_value(&self) -> Result<Value> {
+        Ok(Value::String(self.render(OutputFormat::Raw)?))
+    }
+}
+
+impl<T: Serialize + ?Sized> Render for T {
+    fn render(&self, format: OutputFormat) -> Result<String> {
+        format.render_serialized(self)
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+/// A value within a table cell, on its own line of raw output or substituted for a `$_`
+/// reference
+pub(crate) fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Render a value as a table. Objects have a column per field, a lone object a row per field,
+/// and anything else is rendered raw
+fn table(value: &Value) -> String {
+    match value {
+        Value::Array(values) if values.iter().all(Value::is_object) && !values.is_empty() => {
+            let mut headers: Vec<&str> = Vec::new();
+            for object in values.iter().filter_map(Value::as_object) {
+                for key in object.keys() {
+                    if !headers.contains(&key.as_str()) {
+                        headers.push(key);
+                    }
+                }
+            }
+            let rows = values
+                .iter()
+                .filter_map(Value::as_object)
+                .map(|object| {
+                    headers
+                        .iter()
+                        .map(|header| object.get(*header).map(cell).unwrap_or_default())
+                        .collect()
+                })
+                .collect::<Vec<Vec<String>>>();
+            let headers = headers.iter().map(|header| header.to_string()).collect();
+            columns(headers, rows)
+        }
+        Value::Object(object) => {
+            let rows = object
+                .iter()
+                .map(|(key, value)| vec![key.clone(), cell(value)])
+                .collect();
+            columns(vec!["field".to_string(), "value".to_string()], rows)
+        }
+        Value::Array(values) => values.iter().map(cell).collect::<Vec<_>>().join("\n"),
+        value => cell(value),
+    }
+}
+
+/// Lay out the rows in aligned columns beneath the headers
+fn columns(headers: Vec<String>, rows: Vec<Vec<String>>) -> String {
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|column| {
+            std::iter::once(&headers)
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let separator = widths.iter().map(|width| "-".repeat(*width)).collect();
+    std::iter::once(headers)
+        .chain(std::iter::once(separator))
+        .chain(rows)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,152 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! The source of input lines for the REPL, either the interactive terminal editor or
+//! a non-interactive script
+
+use rustyline::completion::Completer;
+use rustyline::config::Configurer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ColorMode, Editor, Helper};
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub(crate) type ReplEditor = Editor<Masking, DefaultHistory>;
+
+/// The editor's helper, which hides the line being typed while masking (i.e. for a password)
+#[derive(Debug, Default)]
+pub(crate) struct Masking {
+    masking: bool,
+}
+
+impl Highlighter for Masking {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.masking {
+            Cow::Owned("*".repeat(line.chars().count()))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        self.masking
+    }
+}
+
+impl Completer for Masking {
+    type Candidate = String;
+}
+
+impl Hinter for Masking {
+    type Hint = String;
+}
+
+impl Validator for Masking {}
+
+impl Helper for Masking {}
+
+/// Shared handles to where the REPL reads lines from. Cloning is cheap, which
+/// allows a read to be moved onto a blocking thread by the async loop
+#[derive(Clone)]
+pub(crate) struct Input {
+    editor: Arc<Mutex<ReplEditor>>,
+    script: Option<Arc<Mutex<Box<dyn BufRead + Send>>>>,
+}
+
+impl std::fmt::Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Input")
+            .field("editor", &self.editor)
+            .field("script", &self.script.is_some())
+            .finish()
+    }
+}
+
+impl Input {
+    pub(crate) fn new(editor: ReplEditor) -> Self {
+        Self {
+            editor: Arc::new(Mutex::new(editor)),
+            script: None,
+        }
+    }
+
+    /// Read lines from the supplied script rather than the interactive editor
+    pub(crate) fn set_script(&mut self, script: Box<dyn BufRead + Send>) {
+        self.script = Some(Arc::new(Mutex::new(script)));
+    }
+
+    /// Whether the input is coming from a script rather than a user at a terminal
+    pub(crate) fn is_script(&self) -> bool {
+        self.script.is_some()
+    }
+
+    /// Lock the editor. A panic while it was held doesn't leave the editor in an
+    /// unusable state, so poisoning is ignored
+    pub(crate) fn editor(&self) -> MutexGuard<'_, ReplEditor> {
+        self.editor
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Replace the editor's command history with the supplied entries, returning the
+    /// entries it replaced
+    pub(crate) fn swap_history(&self, entries: Vec<String>) -> Vec<String> {
+        let mut editor = self.editor();
+        let replaced = editor.history().iter().cloned().collect();
+        let _ = editor.clear_history();
+        for entry in entries {
+            let _ = editor.add_history_entry(entry);
+        }
+        replaced
+    }
+
+    /// Read the next line of input, displaying the prompt if interactive
+    pub(crate) fn read_line(&self, prompt: &str) -> Result<String, ReadlineError> {
+        match &self.script {
+            Some(script) => read_script_line(script),
+            None => self.editor().readline(prompt),
+        }
+    }
+
+    /// Read the next line of input with what's typed masked, displaying the prompt if
+    /// interactive
+    pub(crate) fn read_masked_line(&self, prompt: &str) -> Result<String, ReadlineError> {
+        if let Some(script) = &self.script {
+            return read_script_line(script);
+        }
+        let mut editor = self.editor();
+        let color_mode = editor.config_mut().color_mode();
+        if let Some(helper) = editor.helper_mut() {
+            helper.masking = true;
+        }
+        // the mask is drawn by the highlighter, so it's needed even when colors are disabled
+        editor.set_color_mode(ColorMode::Forced);
+        let line = editor.readline(prompt);
+        editor.set_color_mode(color_mode);
+        if let Some(helper) = editor.helper_mut() {
+            helper.masking = false;
+        }
+        line
+    }
+}
+
+/// Read the script's next line, without the trailing newline
+fn read_script_line(script: &Mutex<Box<dyn BufRead + Send>>) -> Result<String, ReadlineError> {
+    let mut script = script
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut line = String::new();
+    if script.read_line(&mut line)? == 0 {
+        return Err(ReadlineError::Eof);
+    }
+    let trimmed = line.trim_end_matches(&['\r', '\n'][..]).len();
+    line.truncate(trimmed);
+    Ok(line)
+}
@@ -0,0 +1,239 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Built-in meta-commands, which are prefixed with ':' so they never clash with the
+//! parser's own commands, and the session state they manage
+
+use std::path::Path;
+
+/// Split option tokens into groups of a flag followed by its values, i.e.
+/// `--env prod --verbose` into `[["--env", "prod"], ["--verbose"]]`
+fn option_groups(tokens: &[&str]) -> Result<Vec<Vec<String>>, String> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    for token in tokens {
+        if is_flag(token) {
+            groups.push(vec![token.to_string()]);
+        } else if let Some(group) = groups.last_mut() {
+            group.push(token.to_string());
+        } else {
+            return Err(format!(
+                "Expected an option (i.e. --name), found '{}'",
+                token
+            ));
+        }
+    }
+    Ok(groups)
+}
+
+fn is_flag(token: &str) -> bool {
+    token.starts_with('-') && token.len() > 1
+}
+
+/// The option a flag token sets, i.e. `--env` for both `--env` and `--env=prod`
+fn option_key(flag: &str) -> &str {
+    flag.split('=').next().unwrap_or(flag)
+}
+
+/// Whether the line's tokens already set the option, before any `--` terminator
+fn sets_option(tokens: &[&str], key: &str) -> bool {
+    tokens
+        .iter()
+        .take_while(|token| **token != "--")
+        .any(|token| is_flag(token) && option_key(token) == key)
+}
+
+/// Argument values pinned for the rest of the session with `:pin`, which are injected
+/// ahead of every line which doesn't set them itself
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Pins {
+    groups: Vec<Vec<String>>,
+}
+
+impl Pins {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Pin the options, replacing any earlier pin of the same option
+    pub(crate) fn pin(&mut self, tokens: &[&str]) -> Result<(), String> {
+        for group in option_groups(tokens)? {
+            let key = option_key(&group[0]).to_string();
+            match self
+                .groups
+                .iter_mut()
+                .find(|pinned| option_key(&pinned[0]) == key)
+            {
+                Some(pinned) => *pinned = group,
+                None => self.groups.push(group),
+            }
+        }
+        Ok(())
+    }
+
+    /// Unpin the named options, or everything when none are named
+    pub(crate) fn unpin(&mut self, tokens: &[&str]) -> Result<(), String> {
+        for token in tokens {
+            if !is_flag(token) {
+                return Err(format!(
+                    "Expected an option (i.e. --name), found '{}'",
+                    token
+                ));
+            }
+            let key = option_key(token);
+            if !self
+                .groups
+                .iter()
+                .any(|pinned| option_key(&pinned[0]) == key)
+            {
+                return Err(format!("'{}' isn't set", key));
+            }
+        }
+        if tokens.is_empty() {
+            self.groups.clear();
+        } else {
+            self.groups
+                .retain(|pinned| !sets_option(tokens, option_key(&pinned[0])));
+        }
+        Ok(())
+    }
+
+    /// The line's tokens preceded by every pinned option the line doesn't override
+    pub(crate) fn apply<'a>(&'a self, tokens: &[&'a str]) -> Vec<&'a str> {
+        self.groups
+            .iter()
+            .filter(|pinned| !sets_option(tokens, option_key(&pinned[0])))
+            .flat_map(|pinned| pinned.iter().map(String::as_str))
+            .chain(tokens.iter().copied())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Pins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pinned: Vec<String> = self.groups.iter().map(|group| group.join(" ")).collect();
+        f.write_str(&pinned.join(" "))
+    }
+}
+
+/// Default argument values for sub-commands set with `:default`, which are injected after the
+/// sub-command's name on every line which doesn't set them itself
+#[derive(Debug, Default)]
+pub(crate) struct Defaults {
+    /// Each sub-command's path (i.e. `deploy`) with its default options, in the order set
+    entries: Vec<(Vec<String>, Pins)>,
+}
+
+/// Split the tokens into the leading sub-command path and the options following it
+fn split_path<'a, 'b>(tokens: &'b [&'a str]) -> (Vec<String>, &'b [&'a str]) {
+    let length = tokens.iter().take_while(|token| !is_flag(token)).count();
+    let path = tokens[..length]
+        .iter()
+        .map(|token| token.to_string())
+        .collect();
+    (path, &tokens[length..])
+}
+
+impl Defaults {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set defaults from a sub-command path followed by its options, i.e.
+    /// `deploy --region us-east-1`, replacing any earlier default of the same options
+    pub(crate) fn set(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let (path, options) = split_path(tokens);
+        if path.is_empty() {
+            return Err("Expected a sub-command before the default options".to_string());
+        }
+        if options.is_empty() {
+            return Err(format!(
+                "Expected options to default for '{}'",
+                path.join(" ")
+            ));
+        }
+        match self.entries.iter_mut().find(|(entry, _)| *entry == path) {
+            Some((_, defaults)) => defaults.pin(options),
+            None => {
+                let mut defaults = Pins::default();
+                defaults.pin(options)?;
+                self.entries.push((path, defaults));
+                Ok(())
+            }
+        }
+    }
+
+    /// Clear the named options of a sub-command, every default of a sub-command when no
+    /// options are named, or everything when nothing is named at all
+    pub(crate) fn clear(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let (path, options) = split_path(tokens);
+        if path.is_empty() {
+            if !options.is_empty() {
+                return Err("Expected a sub-command before the options to clear".to_string());
+            }
+            self.entries.clear();
+            return Ok(());
+        }
+        match self.entries.iter_mut().find(|(entry, _)| *entry == path) {
+            Some((_, defaults)) => defaults.unpin(options)?,
+            None => return Err(format!("'{}' has no defaults", path.join(" "))),
+        }
+        self.entries.retain(|(_, defaults)| !defaults.is_empty());
+        Ok(())
+    }
+
+    /// The line's tokens with the defaults of the longest sub-command path it starts with
+    /// inserted after that path, unless the line sets them itself. The path is matched against
+    /// the `resolved` tokens, which name each sub-command in full (i.e. rather than by an alias)
+    pub(crate) fn apply<'a>(&'a self, tokens: &[&'a str], resolved: &[String]) -> Vec<&'a str> {
+        let matching = self
+            .entries
+            .iter()
+            .filter(|(path, _)| {
+                path.len() <= resolved.len() && path.iter().zip(resolved).all(|(a, b)| a == b)
+            })
+            .max_by_key(|(path, _)| path.len());
+        match matching {
+            Some((path, defaults)) => {
+                let (head, rest) = tokens.split_at(path.len());
+                head.iter().copied().chain(defaults.apply(rest)).collect()
+            }
+            None => tokens.to_vec(),
+        }
+    }
+
+    /// Load the defaults saved to the file, one sub-command's defaults per line
+    pub(crate) fn load(path: &Path) -> std::io::Result<Self> {
+        let mut defaults = Self::default();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if !tokens.is_empty() {
+                defaults
+                    .set(&tokens)
+                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            }
+        }
+        Ok(defaults)
+    }
+
+    /// Save the defaults to the file, in the format read by [Defaults::load]
+    pub(crate) fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut contents = self.to_string();
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        std::fs::write(path, contents)
+    }
+}
+
+impl std::fmt::Display for Defaults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(path, defaults)| format!("{} {}", path.join(" "), defaults))
+            .collect();
+        f.write_str(&entries.join("\n"))
+    }
+}
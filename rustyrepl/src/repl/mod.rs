@@ -6,122 +6,564 @@
 use anyhow::Result;
 use log::{debug, error, info, warn};
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::ExternalPrinter;
 use std::{
+    collections::HashMap,
+    io::{BufRead, IsTerminal, Write},
     marker::PhantomData,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use crate::commands::AsyncReplCommandProcessor;
 use crate::commands::ReplCommandProcessor;
+use crate::console::{Console, ScriptConfirmation, CONFIRMATION_QUESTION};
+use crate::context::{Processed, ReplContext};
+use crate::hooks::ReplHooks;
+use crate::middleware::{BoxFuture, Next, ReplMiddleware};
+use crate::output::{HeldOutput, OutputBuffer, ReplOutput};
+use crate::parser::{
+    check_global_options, parse_with_bypass, parses, ClapParser, CommandParser, ParseOutcome,
+};
+use crate::printer::ReplPrinter;
+use crate::render::OutputFormat;
+use crate::runtime::{BlockingSpawner, Spawner};
+use crate::status::{is_cancelled, status_of, StatusError, FAILURE, INVALID, SUCCESS};
+pub(crate) use input::Input;
+use input::{Masking, ReplEditor};
+use meta::{Defaults, Pins};
+use redirect::Redirection;
+use results::Results;
+use watch::{Screen, Terminal, Watch};
 
 const DEFAULT_HISTORY_FILE_NAME: &str = ".repl_history";
 
+/// The extension appended to the history file's name for the file saving `:default` values
+const DEFAULTS_FILE_EXTENSION: &str = "defaults";
+
+/// Commands built into the REPL, which are suggested alongside the parser's own commands
+const META_COMMANDS: &[&str] = &[
+    "help",
+    ":pin",
+    ":unpin",
+    ":default",
+    ":undefault",
+    ":format",
+    ":results",
+    ":set",
+    "watch",
+];
+
+mod input;
+mod meta;
+mod pager;
+mod redirect;
+mod results;
+mod suggest;
+mod watch;
+
 #[cfg(test)]
 mod tests;
 
-#[cfg(not(feature = "async"))]
-macro_rules! get_specific_processing_call {
-    ($self:ident, $cli:expr) => {
-        $self.command_processor.process_command($cli)?;
+macro_rules! get_specific_readline_call {
+    (sync, $self:ident, $prompt:expr) => {
+        $self.input.read_line(&$prompt)
     };
+    (async, $self:ident, $prompt:expr) => {{
+        let input = $self.input.clone();
+        let prompt = $prompt.to_string();
+        $self.spawner.run(move || input.read_line(&prompt)).await?
+    }};
 }
 
-#[cfg(feature = "async")]
 macro_rules! get_specific_processing_call {
-    ($self:ident, $cli:expr) => {
-        $self.command_processor.process_command($cli).await?
+    (sync, $self:ident, $cli:expr, $bypassed:expr) => {
+        $self.dispatch($cli, $bypassed)
+    };
+    (async, $self:ident, $cli:expr, $bypassed:expr) => {
+        $self.dispatch_async($cli, $bypassed).await
+    };
+}
+
+macro_rules! get_specific_unparsed_call {
+    (sync, $self:ident, $line:expr) => {
+        $self.command_processor.process_unparsed($line)
+    };
+    (async, $self:ident, $line:expr) => {
+        $self.command_processor.process_unparsed($line).await
+    };
+}
+
+macro_rules! get_specific_context_call {
+    (sync, $context:expr, $tokens:expr, $line:expr, $console:expr) => {
+        $context.process($tokens, $line, $console)
+    };
+    (async, $context:expr, $tokens:expr, $line:expr, $console:expr) => {
+        $context.process_async($tokens, $line, $console).await
+    };
+}
+
+/// Wait out the interval between runs of a watched command, returning whether the user
+/// stopped watching
+macro_rules! get_specific_wait_call {
+    (sync, $self:ident, $interval:expr) => {
+        watch::wait($interval)?
     };
+    (async, $self:ident, $interval:expr) => {{
+        let interval = $interval;
+        $self.spawner.run(move || watch::wait(interval)).await??
+    }};
 }
 
-/// The [process_block!] macro toggles between the async and sync
-/// definitions of the logic in the process() function. Depending on the
-/// feature enabled, we will select the correct underlying implementation
-/// as necessary
+/// Whether the command requires confirmation, which the user declined
+macro_rules! get_specific_declined_call {
+    (sync, $self:ident, $cli:expr) => {
+        $self.command_processor.requires_confirmation($cli)
+            && !$self
+                .console(false)
+                .confirm_blocking(CONFIRMATION_QUESTION)?
+    };
+    (async, $self:ident, $cli:expr) => {
+        $self.command_processor.requires_confirmation($cli)
+            && !$self.console(true).confirm(CONFIRMATION_QUESTION).await?
+    };
+}
+
+/// Run the body to completion, catching any error it returns so the session can
+/// always be closed afterwards
+macro_rules! get_specific_session_call {
+    (sync, $body:block) => {
+        (|| -> Result<()> { $body })()
+    };
+    (async, $body:block) => {
+        async { $body }.await
+    };
+}
+
+macro_rules! is_async_mode {
+    (sync) => {
+        false
+    };
+    (async) => {
+        true
+    };
+}
+
+/// Re-run the watched command until the user stops watching, redrawing its output (through
+/// the line's filters, if any) after each run
+macro_rules! process_watch {
+    ( $self:ident, $mode:tt, $watch:expr, $redirection:expr ) => {{
+        let watch: Watch = $watch;
+        let redirection: Option<Redirection> = $redirection;
+        let tokens: Vec<String> = {
+            let tokens: Vec<&str> = watch.command().iter().map(String::as_str).collect();
+            let tokens = $self
+                .defaults
+                .apply(&tokens, &$self.parser.resolve(&tokens));
+            $self
+                .pins
+                .apply(&tokens)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        };
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        let (outcome, bypassed) = parse_with_bypass(&mut *$self.parser, &tokens);
+        match outcome {
+            ParseOutcome::Command(cli) => {
+                if !bypassed && get_specific_declined_call!($mode, $self, &cli) {
+                    info!("Watch cancelled");
+                    $self.fail(FAILURE)?;
+                } else {
+                    let mut screen = Screen::new(&watch);
+                    let _terminal = Terminal::enter()?;
+                    let mut first = Some(cli);
+                    loop {
+                        // The same line parses to the same command on every run
+                        let cli = match first.take() {
+                            Some(cli) => cli,
+                            None => match parse_with_bypass(&mut *$self.parser, &tokens).0 {
+                                ParseOutcome::Command(cli) => cli,
+                                _ => break,
+                            },
+                        };
+                        if let Err(err) = $self.hooks.before_command(&cli) {
+                            warn!("Command denied: {}", err);
+                            $self.fail(FAILURE)?;
+                            break;
+                        }
+                        let captured = OutputBuffer::default();
+                        let redirected = $self
+                            .output
+                            .clone()
+                            .capture(captured.clone())
+                            .capture_err(captured.clone());
+                        let output = std::mem::replace(&mut $self.output, redirected);
+                        let started = std::time::Instant::now();
+                        let result = get_specific_processing_call!($mode, $self, cli, bypassed);
+                        $self.hooks.after_command(&result, started.elapsed());
+                        $self.output = output;
+                        $self.record_result();
+                        let mut lines: Vec<String> =
+                            captured.take().lines().map(String::from).collect();
+                        match result {
+                            Ok(()) => $self.status = SUCCESS,
+                            Err(err) => {
+                                $self.status = status_of(&err);
+                                lines.push(format!("Error: {}", err));
+                            }
+                        }
+                        if let Some(redirection) = &redirection {
+                            lines = redirection.filter(lines);
+                        }
+                        screen.draw(lines)?;
+                        if get_specific_wait_call!($mode, $self, watch.interval()) {
+                            break;
+                        }
+                    }
+                }
+            }
+            ParseOutcome::Help(help) => {
+                writeln!($self.output.out(), "{}", help)?;
+                $self.status = SUCCESS;
+            }
+            ParseOutcome::Error(err) => {
+                warn!("Invalid command to watch\r\n{}", err);
+                $self.fail(INVALID)?;
+            }
+        }
+    }};
+}
+
+/// The [process_block!] macro holds the logic shared between the sync
+/// process() and async process_async() functions. The `$mode` token (`sync`
+/// or `async`) selects how the underlying processor is invoked
 macro_rules! process_block {
-    ( $self:ident ) => {
+    ( $self:ident, $mode:tt ) => {
         {
-            loop {
-                let readline = $self.editor.readline(&$self.prompt);
-                match readline {
-                    Ok(line) => {
-                        let parts: Vec<&str> = line.split(' ').collect();
-                        let mut command = String::new();
-                        if let Some(head) = parts.first() {
-                            command = String::from(*head);
-                        }
-                        match command.to_lowercase().as_ref() {
-                            "" => {} // Loop, someone hit enter needlessly
-                            maybe_quit if $self.command_processor.is_quit(maybe_quit) => break, // check for quit/exit
-                            _ => {
-                                // We're only appending valid commands to the history trail
-                                $self.editor.add_history_entry(line.as_str());
-
-                                let mut cmd_parts: Vec<&str> = vec!["repl-interface"];
-                                cmd_parts.extend(line.split(' ').collect::<Vec<_>>().iter().copied());
-                                match C::try_parse_from(cmd_parts.into_iter()) {
-                                    Ok(cli) => {
-                                        // Call the underlying processing logic
-                                        get_specific_processing_call!($self, cli);
+            $self.hooks.on_start()?;
+            let result = get_specific_session_call!($mode, {
+                // A suggested correction the user chose to run in place of their next line
+                let mut suggested: Option<String> = None;
+                loop {
+                    $self.finish_redirection();
+                    let readline = match suggested.take() {
+                        Some(line) => Ok(line),
+                        None => get_specific_readline_call!($mode, $self, $self.current_prompt()),
+                    };
+                    match readline {
+                        Ok(line) => {
+                            let parts: Vec<&str> = line.split(' ').collect();
+                            let mut command = String::new();
+                            if let Some(head) = parts.first() {
+                                command = String::from(*head);
+                            }
+                            match command.to_lowercase().as_ref() {
+                                "" => {} // Loop, someone hit enter needlessly
+                                "exit" if !$self.contexts.is_empty() => $self.pop_context(), // leave the current context
+                                "end" if !$self.contexts.is_empty() => while !$self.contexts.is_empty() {
+                                    $self.pop_context(); // return to the top level
+                                },
+                                maybe_quit if $self.command_processor.is_quit(maybe_quit) => break, // check for quit/exit
+                                _ => {
+                                    // We're only appending valid commands to the history trail
+                                    if !$self.input.is_script() {
+                                        let _ = $self.input.editor().add_history_entry(line.as_str());
                                     }
-                                    Err(clap_err) => match clap::Error::kind(&clap_err) {
 
-                                        clap::error::ErrorKind::DisplayHelp | clap::error::ErrorKind::DisplayVersion => {
-                                            println!("{}", clap_err);
+                                    let line_tokens: Vec<&str> = line.split_whitespace().collect();
+                                    let (tokens, mut redirection) = Redirection::split(&line_tokens);
+                                    let mut expanded = $self.expand(tokens);
+                                    // Only commands are redirected, so lines left to process_unparsed
+                                    // (i.e. expressions comparing with `>`) are kept whole
+                                    if redirection.is_some() && !expanded.as_ref().is_ok_and(|tokens| $self.parses(tokens)) {
+                                        redirection = None;
+                                        expanded = $self.expand(&line_tokens);
+                                    }
+                                    let expanded = match expanded {
+                                        Ok(expanded) => expanded,
+                                        Err(err) => {
+                                            warn!("{}", err);
+                                            $self.fail(INVALID)?;
+                                            continue;
+                                        }
+                                    };
+                                    match redirection.transpose() {
+                                        Ok(redirection) => $self.begin_redirection(redirection),
+                                        Err(err) => {
+                                            warn!("Invalid redirection: {}", err);
+                                            $self.fail(INVALID)?;
+                                            continue;
+                                        }
+                                    }
+                                    let tokens: Vec<&str> = expanded.iter().map(String::as_str).collect();
+                                    if $self.process_meta(&tokens)? {
+                                        continue;
+                                    }
+                                    if $self.xtrace {
+                                        let _ = writeln!($self.output.err(), "+ {}", tokens.join(" "));
+                                    }
+                                    if $self.is_watch(&tokens) {
+                                        let redirection = $self.end_redirection();
+                                        match Watch::parse(&tokens[1..]) {
+                                            Err(err) => {
+                                                warn!("Invalid watch: {}", err);
+                                                $self.fail(INVALID)?;
+                                            }
+                                            Ok(_) if redirection.as_ref().is_some_and(Redirection::writes_file) => {
+                                                warn!("The output of a watched command can't be written to a file");
+                                                $self.fail(INVALID)?;
+                                            }
+                                            Ok(_) if !$self.watches() => {
+                                                warn!("Commands can only be watched from an interactive terminal");
+                                                $self.fail(FAILURE)?;
+                                            }
+                                            Ok(watch) => process_watch!($self, $mode, watch, redirection),
+                                        }
+                                        continue;
+                                    }
+                                    let console = $self.console(is_async_mode!($mode));
+                                    if let Some(frame) = $self.contexts.last_mut() {
+                                        let processed = get_specific_context_call!($mode, frame.context, &tokens, &line, &console);
+                                        $self.record_result();
+                                        match $self.settle(processed)? {
+                                            Processed::Done => {}
+                                            Processed::Entered(context) => $self.push_context(context, is_async_mode!($mode)),
+                                            Processed::Cancelled => $self.fail(FAILURE)?,
+                                            Processed::Invalid => $self.fail(INVALID)?,
+                                        }
+                                        continue;
+                                    }
+                                    let tokens = $self.defaults.apply(&tokens, &$self.parser.resolve(&tokens));
+                                    let tokens = $self.pins.apply(&tokens);
+                                    let (outcome, bypassed) = parse_with_bypass(&mut *$self.parser, &tokens);
+                                    match outcome {
+                                        ParseOutcome::Command(cli) => match $self.command_processor.enter_context(&cli) {
+                                            Some(context) => $self.push_context(context, is_async_mode!($mode)),
+                                            None => {
+                                                if let Err(err) = $self.hooks.before_command(&cli) {
+                                                    warn!("Command denied: {}", err);
+                                                    $self.fail(FAILURE)?;
+                                                    continue;
+                                                }
+                                                if !bypassed && get_specific_declined_call!($mode, $self, &cli) {
+                                                    info!("Command cancelled");
+                                                    $self.fail(FAILURE)?;
+                                                    continue;
+                                                }
+                                                // Call the underlying processing logic
+                                                let started = std::time::Instant::now();
+                                                let result = get_specific_processing_call!($mode, $self, cli, bypassed);
+                                                $self.hooks.after_command(&result, started.elapsed());
+                                                $self.record_result();
+                                                $self.settle(result)?;
+                                            }
+                                        },
+                                        ParseOutcome::Help(help) => {
+                                            writeln!($self.output.out(), "{}", help)?;
+                                            $self.status = SUCCESS;
                                         }
-                                        _ => {
-                                            warn!(
-                                                "Invalid command (type 'help' for the help menu\r\n{}",
-                                                clap_err
-                                            );
+                                        ParseOutcome::Error(err) => {
+                                            // Give the processor first refusal on lines which aren't commands
+                                            match get_specific_unparsed_call!($mode, $self, &line) {
+                                                Ok(false) => {
+                                                    warn!(
+                                                        "Invalid command (type 'help' for the help menu\r\n{}",
+                                                        err
+                                                    );
+                                                    if let Some(suggestion) = $self.suggest(&line) {
+                                                        if $self.suggestion_prompt {
+                                                            let question = format!("Did you mean '{}'? [y/N] ", suggestion);
+                                                            let answer = get_specific_readline_call!($mode, $self, question);
+                                                            if answer.map_or(false, |answer| {
+                                                                matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+                                                            }) {
+                                                                suggested = Some(suggestion);
+                                                            }
+                                                        } else {
+                                                            warn!("Did you mean '{}'?", suggestion);
+                                                        }
+                                                    }
+                                                    $self.fail(INVALID)?;
+                                                }
+                                                handled => {
+                                                    $self.settle(handled)?;
+                                                }
+                                            }
                                         }
-                                    },
+                                    }
                                 }
                             }
                         }
-                    }
-                    Err(ReadlineError::Interrupted) => break, // CTRL-C
-                    Err(ReadlineError::Eof) => break,         // CTRL-D
-                    Err(err) => {
-                        error!("Error: {:?}", err);
-                        break;
+                        Err(ReadlineError::Interrupted) => break, // CTRL-C
+                        Err(ReadlineError::Eof) => break,         // CTRL-D
+                        Err(err) => {
+                            error!("Error: {:?}", err);
+                            break;
+                        }
                     }
                 }
-            }
+                Ok::<(), anyhow::Error>(())
+            });
+            $self.finish_redirection();
             $self.close_history();
-            Ok(())
+            let exited = $self.hooks.on_exit();
+            result.and(exited)
         }
     };
 }
 
-/// Represents the REPL interface and processing loop
+/// A nested context entered by the REPL, with the command history of the context it was
+/// entered from
 #[derive(Debug)]
-pub struct Repl<C>
-where
-    C: clap::Parser,
-{
-    /// The REPL editor interface for the command prompt
-    editor: Editor<()>,
+struct ContextFrame {
+    context: ReplContext,
+    outer_history: Vec<String>,
+}
+
+/// A line's output being captured for redirection, with the REPL's output to restore once
+/// the line's been processed
+#[derive(Debug)]
+struct Redirected {
+    redirection: Redirection,
+    captured: OutputBuffer,
+    output: ReplOutput,
+}
+
+/// Represents the REPL interface and processing loop.
+///
+/// The command processor `P` is either a [ReplCommandProcessor], which enables
+/// [Repl::process], or an [AsyncReplCommandProcessor] which enables [Repl::process_async].
+/// It defaults to a boxed synchronous processor
+#[derive(Debug)]
+pub struct Repl<C, P = Box<dyn ReplCommandProcessor<C>>> {
+    /// The source of input lines, the REPL editor interface for the command prompt or a script
+    input: Input,
 
     /// The history file
     history: Option<PathBuf>,
 
     /// User-provided command processor responsible for processing parsed command instructions and
     /// executing on them
-    command_processor: Box<dyn ReplCommandProcessor<C>>,
+    command_processor: P,
 
     /// The prompt to the interface (defaults to ">>")
     prompt: String,
 
+    /// Handle for printing above the prompt, created on first request
+    printer: Option<ReplPrinter>,
+
+    /// Where blocking reads are executed by the async REPL loop
+    spawner: Spawner,
+
+    /// Parser of each line into the command structure
+    parser: Box<dyn CommandParser<C>>,
+
+    /// Whether to ask if a suggested correction should be run, rather than only displaying it
+    suggestion_prompt: bool,
+
+    /// Arguments pinned for the rest of the session, which are injected into every line
+    pins: Pins,
+
+    /// Default argument values for sub-commands, which are injected into lines running them
+    defaults: Defaults,
+
+    /// Where the defaults are saved, alongside the history file, if enabled
+    defaults_file: Option<PathBuf>,
+
+    /// Hooks run around the session and each top-level command
+    hooks: Box<dyn ReplHooks<C>>,
+
+    /// How confirmations are answered when running a script
+    script_confirmation: ScriptConfirmation,
+
+    /// Where command output (and the REPL's own, i.e. help) is written
+    output: ReplOutput,
+
+    /// The format command results are rendered in
+    format: OutputFormat,
+
+    /// The redirection of the line being processed, if any
+    redirected: Option<Redirected>,
+
+    /// Whether output taller than the terminal is paged
+    pager: bool,
+
+    /// The results of recent commands, referenced with `$_`
+    results: Results,
+
+    /// The number of commands processed, which numbers their results
+    commands: usize,
+
+    /// Where the command being processed leaves its result
+    result: Arc<Mutex<Option<serde_json::Value>>>,
+
+    /// The exit status of the last command, read with `$?`
+    status: i32,
+
+    /// Whether any failure ends the session (`:set -e`), or none do (`:set +e`). By default
+    /// only a failed command does
+    exit_on_error: Option<bool>,
+
+    /// Whether each line is echoed to the error stream before it's run (`:set -x`)
+    xtrace: bool,
+
+    /// The middleware chain wrapping the dispatch of each top-level command, outermost first
+    middleware: Vec<Box<dyn ReplMiddleware<C>>>,
+
+    /// The stack of nested contexts entered, with the innermost last
+    contexts: Vec<ContextFrame>,
+
+    /// The command history of each context left during the session, by the context's name
+    context_histories: HashMap<String, Vec<String>>,
+
     /// Phantom holder for the command structure enum
     _command_type: PhantomData<C>,
 }
 
-impl<C> Repl<C>
-where
-    C: clap::Parser,
-{
+impl<C, P> Repl<C, P> {
     // =================== Private Functions =================== //
 
+    /// Construct the REPL around an arbitrary command parser
+    fn build(
+        parser: Box<dyn CommandParser<C>>,
+        command_processor: P,
+        history_file: Option<String>,
+        prompt: Option<String>,
+    ) -> Result<Self> {
+        let history_path = Self::get_history_file_path(history_file);
+        let editor = Self::get_editor(&history_path)?;
+        Ok(Self {
+            input: Input::new(editor),
+            history: history_path,
+            command_processor,
+            prompt: prompt.unwrap_or_else(|| "$ ".to_string()),
+            printer: None,
+            spawner: Spawner::default(),
+            parser,
+            suggestion_prompt: false,
+            pins: Pins::default(),
+            defaults: Defaults::default(),
+            defaults_file: None,
+            hooks: Box::new(()),
+            script_confirmation: ScriptConfirmation::default(),
+            output: ReplOutput::default(),
+            format: OutputFormat::default(),
+            redirected: None,
+            pager: false,
+            results: Results::default(),
+            commands: 0,
+            result: Arc::default(),
+            status: SUCCESS,
+            exit_on_error: None,
+            xtrace: false,
+            middleware: Vec::new(),
+            contexts: Vec::new(),
+            context_histories: HashMap::new(),
+            _command_type: PhantomData,
+        })
+    }
+
     /// Format the history file name to a full path for rustyline
     fn get_history_file_path(history_file_name: Option<String>) -> Option<PathBuf> {
         if let Some(history_file) = &history_file_name {
@@ -164,8 +606,9 @@ where
     }
 
     /// Retrieve the rustyline editor with history loaded (if possible)
-    fn get_editor(history: &Option<PathBuf>) -> Result<Editor<()>> {
-        let mut rl = Editor::<()>::new();
+    fn get_editor(history: &Option<PathBuf>) -> Result<ReplEditor> {
+        let mut rl = ReplEditor::new()?;
+        rl.set_helper(Some(Masking::default()));
 
         if let Some(history_file) = history {
             match rl.load_history(history_file.as_os_str()) {
@@ -177,51 +620,654 @@ where
         Ok(rl)
     }
 
+    /// Number the command just processed, keeping its result (if it rendered one)
+    fn record_result(&mut self) {
+        self.commands += 1;
+        let result = self
+            .result
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        if let Some(value) = result {
+            self.results.push(self.commands, value);
+        }
+    }
+
+    /// Set the exit status from the command's result. A failure ends the session unless
+    /// `:set +e` is on, in which case it's logged and the session continues. A cancelled
+    /// command is treated like a declined confirmation
+    fn settle<T: Default>(&mut self, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(value) => {
+                self.status = SUCCESS;
+                Ok(value)
+            }
+            Err(err) if is_cancelled(&err) => {
+                info!("{}", err);
+                self.fail(FAILURE)?;
+                Ok(T::default())
+            }
+            Err(err) => {
+                self.status = status_of(&err);
+                if self.exit_on_error != Some(false) {
+                    return Err(err);
+                }
+                warn!("Command failed with status {}: {}", self.status, err);
+                Ok(T::default())
+            }
+        }
+    }
+
+    /// Set the failed status of a line which didn't run a command (i.e. it was invalid, denied
+    /// or cancelled), ending the session when `:set -e` is on
+    fn fail(&mut self, status: i32) -> Result<()> {
+        self.status = status;
+        if self.exit_on_error == Some(true) {
+            return Err(StatusError::new(
+                status,
+                format!("Stopped after a line failed with status {}", status),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Replace `$?` with the exit status of the last command, and each token referencing a
+    /// result with the referenced value
+    fn expand(&self, tokens: &[&str]) -> Result<Vec<String>, String> {
+        let status = self.status.to_string();
+        let tokens: Vec<String> = tokens
+            .iter()
+            .map(|token| token.replace("$?", &status))
+            .collect();
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        self.results.expand(&tokens)
+    }
+
+    /// Whether the line watches a command. The built-in `watch` gives way to an application
+    /// command of the same name, and isn't available within a context
+    fn is_watch(&mut self, tokens: &[&str]) -> bool {
+        self.contexts.is_empty()
+            && tokens.first() == Some(&"watch")
+            && !self
+                .parser
+                .command_names()
+                .iter()
+                .any(|name| name == "watch")
+    }
+
+    /// Whether the expanded line is a meta-command, or parses as a command (or its help) in
+    /// the current context
+    fn parses(&mut self, tokens: &[String]) -> bool {
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        if tokens.first().is_some_and(|token| token.starts_with(':')) {
+            return true;
+        }
+        if let Some(frame) = self.contexts.last_mut() {
+            return frame.context.parses(&tokens);
+        }
+        if self.is_watch(&tokens) {
+            return Watch::parse(&tokens[1..]).is_ok();
+        }
+        let tokens = self.defaults.apply(&tokens, &self.parser.resolve(&tokens));
+        let tokens = self.pins.apply(&tokens);
+        parses(&mut *self.parser, &tokens)
+    }
+
+    /// Pin the options, once the parser accepts them ahead of any command
+    fn pin(&mut self, options: &[&str]) -> Result<(), String> {
+        let mut pins = self.pins.clone();
+        pins.pin(options)?;
+        check_global_options(&mut *self.parser, options)?;
+        self.pins = pins;
+        Ok(())
+    }
+
+    /// Switch the shell-style options on (`-e`, `-x`) or off (`+e`, `+x`), which may be
+    /// combined (i.e. `-ex`)
+    fn set_options(&mut self, options: &[&str]) -> Result<(), String> {
+        for option in options {
+            let (enabled, flags) = match (option.strip_prefix('-'), option.strip_prefix('+')) {
+                (Some(flags), _) if !flags.is_empty() => (true, flags),
+                (_, Some(flags)) if !flags.is_empty() => (false, flags),
+                _ => {
+                    return Err(format!(
+                        "Expected an option like -e or +x, found '{}'",
+                        option
+                    ))
+                }
+            };
+            for flag in flags.chars() {
+                match flag {
+                    'e' => self.exit_on_error = Some(enabled),
+                    'x' => self.xtrace = enabled,
+                    _ => return Err(format!("Unknown option '{}', expected e or x", flag)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether long output is paged, which is never the case for scripts or when the output
+    /// isn't a terminal
+    fn pages(&self) -> bool {
+        self.pager
+            && !self.input.is_script()
+            && self.output.is_terminal()
+            && std::io::stdout().is_terminal()
+    }
+
+    /// Capture the output of the line being processed, for the redirection (if any) to filter
+    /// and write, and for the pager
+    fn begin_redirection(&mut self, redirection: Option<Redirection>) {
+        let redirection = match redirection {
+            Some(redirection) if self.pages() => redirection.paged(),
+            Some(redirection) => redirection,
+            None if self.pages() => Redirection::default().paged(),
+            None => return,
+        };
+        let captured = OutputBuffer::default();
+        let redirected = self.output.clone().capture(captured.clone());
+        let output = std::mem::replace(&mut self.output, redirected);
+        self.redirected = Some(Redirected {
+            redirection,
+            captured,
+            output,
+        });
+    }
+
+    /// Restore the REPL's output once the redirected line has been processed, writing the
+    /// line's captured output through the redirection
+    fn finish_redirection(&mut self) {
+        if let Some(redirected) = self.redirected.take() {
+            self.output = redirected.output;
+            let captured = redirected.captured.take();
+            if let Err(err) = redirected.redirection.finish(&captured, self.output.out()) {
+                warn!("Failed to redirect output: {}", err);
+            }
+        }
+    }
+
+    /// Restore the REPL's output without writing anything through the line's redirection,
+    /// which is returned for the line to apply itself
+    fn end_redirection(&mut self) -> Option<Redirection> {
+        let redirected = self.redirected.take()?;
+        self.output = redirected.output;
+        Some(redirected.redirection)
+    }
+
+    /// Whether commands can be watched, which redraws the terminal so is never the case for
+    /// scripts or when the output isn't a terminal
+    fn watches(&self) -> bool {
+        !self.input.is_script() && self.output.is_terminal() && std::io::stdout().is_terminal()
+    }
+
     /// Close the history file + save all valid command history (if available)
     fn close_history(&mut self) {
+        // Leave any contexts so the top-level history is what's saved
+        while !self.contexts.is_empty() {
+            self.pop_context();
+        }
         if let Some(history_path) = &self.history {
-            match self.editor.save_history(history_path.as_os_str()) {
+            match self.input.editor().save_history(history_path.as_os_str()) {
                 Ok(_) => info!("REPL command history updated"),
                 Err(err) => warn!("Failed to safe REPL command history with error '{}'", err),
             }
         }
+        if let Some(defaults_path) = &self.defaults_file {
+            match self.defaults.save(defaults_path) {
+                Ok(_) => info!("REPL defaults updated"),
+                Err(err) => warn!("Failed to save REPL defaults with error '{}'", err),
+            }
+        }
+    }
+
+    /// Enter the nested context, swapping in its command history. Asynchronous contexts can't
+    /// be entered from the synchronous loop
+    fn push_context(&mut self, context: ReplContext, asynchronous: bool) {
+        if context.is_async() && !asynchronous {
+            warn!(
+                "The '{}' context is asynchronous, so can only be entered with Repl::process_async",
+                context.name()
+            );
+            return;
+        }
+        let history = self
+            .context_histories
+            .remove(context.name())
+            .unwrap_or_default();
+        let outer_history = self.input.swap_history(history);
+        self.contexts.push(ContextFrame {
+            context,
+            outer_history,
+        });
+    }
+
+    /// Leave the innermost context, restoring the command history of the context it was
+    /// entered from
+    fn pop_context(&mut self) {
+        if let Some(frame) = self.contexts.pop() {
+            let history = self.input.swap_history(frame.outer_history);
+            self.context_histories
+                .insert(frame.context.name().to_string(), history);
+        }
+    }
+
+    /// The prompt to display, which is the current context's or the REPL's own preceded by
+    /// any pinned arguments, with `$?` replaced by the last command's exit status
+    fn current_prompt(&self) -> String {
+        let prompt = if let Some(frame) = self.contexts.last() {
+            frame.context.prompt().to_string()
+        } else if self.pins.is_empty() {
+            self.prompt.clone()
+        } else {
+            format!("[{}] {}", self.pins, self.prompt)
+        };
+        prompt.replace("$?", &self.status.to_string())
+    }
+
+    /// Execute the line if it's one of the REPL's built-in meta-commands, returning whether it
+    /// was. Anything else, including unknown meta-commands, is left to the parser. An invalid
+    /// meta-command sets `$?` like any other invalid line
+    fn process_meta(&mut self, tokens: &[&str]) -> Result<bool> {
+        let executed = match tokens {
+            [":pin"] if self.pins.is_empty() => {
+                let _ = writeln!(self.output.out(), "No arguments are pinned");
+                Ok(())
+            }
+            [":pin"] => {
+                let _ = writeln!(self.output.out(), "{}", self.pins);
+                Ok(())
+            }
+            [":pin", options @ ..] => self
+                .pin(options)
+                .map_err(|err| format!("Invalid pin: {}", err)),
+            [":unpin", options @ ..] => self
+                .pins
+                .unpin(options)
+                .map_err(|err| format!("Invalid unpin: {}", err)),
+            [":default"] if self.defaults.is_empty() => {
+                let _ = writeln!(self.output.out(), "No defaults are set");
+                Ok(())
+            }
+            [":default"] => {
+                let _ = writeln!(self.output.out(), "{}", self.defaults);
+                Ok(())
+            }
+            [":default", defaults @ ..] => self
+                .defaults
+                .set(defaults)
+                .map_err(|err| format!("Invalid default: {}", err)),
+            [":undefault", defaults @ ..] => self
+                .defaults
+                .clear(defaults)
+                .map_err(|err| format!("Invalid undefault: {}", err)),
+            [":format"] => {
+                let _ = writeln!(self.output.out(), "{}", self.format);
+                Ok(())
+            }
+            [":results"] if self.results.is_empty() => {
+                let _ = writeln!(self.output.out(), "No results yet");
+                Ok(())
+            }
+            [":results"] => {
+                let _ = writeln!(self.output.out(), "{}", self.results);
+                Ok(())
+            }
+            [":set"] => {
+                let option = |enabled: bool, flag: char| {
+                    format!("{}{}", if enabled { '-' } else { '+' }, flag)
+                };
+                // `e` is neither on nor off until it's set
+                let options: Vec<String> = self
+                    .exit_on_error
+                    .map(|enabled| option(enabled, 'e'))
+                    .into_iter()
+                    .chain(std::iter::once(option(self.xtrace, 'x')))
+                    .collect();
+                let _ = writeln!(self.output.out(), "{}", options.join(" "));
+                Ok(())
+            }
+            [":set", options @ ..] => self
+                .set_options(options)
+                .map_err(|err| format!("Invalid option: {}", err)),
+            [":format", format] => format
+                .parse()
+                .map(|format| self.format = format)
+                .map_err(|err: anyhow::Error| err.to_string()),
+            _ => return Ok(false),
+        };
+        match executed {
+            Ok(()) => self.status = SUCCESS,
+            Err(err) => {
+                warn!("{}", err);
+                self.fail(INVALID)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// A handle for asking the user questions and writing output, which reads on the
+    /// blocking spawner from the asynchronous loop. Output held back to page it is written
+    /// out before each question
+    fn console(&self, asynchronous: bool) -> Console {
+        let spawner = asynchronous.then(|| self.spawner.clone());
+        let held = self
+            .redirected
+            .as_ref()
+            .filter(|redirected| redirected.redirection.only_pages())
+            .map(|redirected| {
+                HeldOutput::new(redirected.captured.clone(), redirected.output.out())
+            });
+        Console::new(
+            self.input.clone(),
+            spawner,
+            self.script_confirmation,
+            self.output.clone(),
+            self.format,
+            self.result.clone(),
+        )
+        .with_held_output(held)
+    }
+
+    /// Suggest a correction for a line which failed to parse, from the parser's commands,
+    /// the REPL's built-in commands and frequently used history which parses
+    fn suggest(&mut self, line: &str) -> Option<String> {
+        let mut commands = self.parser.command_names();
+        commands.extend(META_COMMANDS.iter().map(|command| command.to_string()));
+        let history: Vec<String> = self.input.editor().history().iter().cloned().collect();
+        suggest::suggest(
+            line,
+            &commands,
+            history.iter().map(String::as_str),
+            |entry| {
+                let tokens: Vec<&str> = entry.split_whitespace().collect();
+                let (tokens, _) = Redirection::split(&tokens);
+                let tokens: Vec<String> = tokens.iter().map(|token| token.to_string()).collect();
+                self.parses(&tokens)
+            },
+        )
     }
 
     // =================== Public API =================== //
 
+    /// Construct a new REPL interface which parses lines with a custom [CommandParser]
+    /// rather than clap, while keeping the REPL's loop and history handling.
+    ///
+    /// * `parser` - The parser of each line's tokens into commands
+    /// * `command_processor` - The processor of parsed commands, either sync or async
+    /// * `history_file` - The optional command history file. Can be a full path, relative path, directory, or just the end filename to utilize
+    /// * `prompt` - The prompt to display to the user to enter input. Defaults to ">>"
+    pub fn from_parser<T: CommandParser<C> + 'static>(
+        parser: T,
+        command_processor: P,
+        history_file: Option<String>,
+        prompt: Option<String>,
+    ) -> Result<Self> {
+        Self::build(Box::new(parser), command_processor, history_file, prompt)
+    }
+
+    /// Read commands from the supplied input, one per line, rather than prompting the
+    /// user interactively. This is useful for piped input, batch files, or tests.
+    /// Scripted commands aren't added to the command history
+    pub fn with_script<R: BufRead + Send + 'static>(mut self, script: R) -> Self {
+        self.input.set_script(Box::new(script));
+        self
+    }
+
+    /// Ask whether to run the "did you mean" suggestion for a mistyped line, rather than only
+    /// displaying it. The answer is read like any other input, so scripts supply it on the
+    /// following line. Answers aren't added to the command history
+    pub fn with_suggestion_prompt(mut self) -> Self {
+        self.suggestion_prompt = true;
+        self
+    }
+
+    /// Save the `:default` values set during the session alongside the history file (with a
+    /// `.defaults` extension), restoring them in the next session. Does nothing without a
+    /// history file
+    pub fn with_saved_defaults(mut self) -> Self {
+        let Some(history) = &self.history else {
+            warn!("REPL defaults can't be saved without a history file");
+            return self;
+        };
+        let mut defaults_path = history.clone().into_os_string();
+        defaults_path.push(".");
+        defaults_path.push(DEFAULTS_FILE_EXTENSION);
+        let defaults_path = PathBuf::from(defaults_path);
+
+        if defaults_path.exists() {
+            match Defaults::load(&defaults_path) {
+                Ok(defaults) => {
+                    info!("REPL defaults loaded");
+                    self.defaults = defaults;
+                }
+                Err(err) => warn!("Failed to load REPL defaults {}", err),
+            }
+        }
+        self.defaults_file = Some(defaults_path);
+        self
+    }
+
+    /// Run the supplied hooks at the start and end of the session, and around each top-level
+    /// command. See [ReplHooks]
+    pub fn with_hooks<H: ReplHooks<C> + 'static>(mut self, hooks: H) -> Self {
+        self.hooks = Box::new(hooks);
+        self
+    }
+
+    /// Write output to the supplied [ReplOutput] rather than the terminal, i.e. to capture,
+    /// tee or redirect it. Commands write to it through their [Console]
+    pub fn with_output(mut self, output: ReplOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Render command results in the supplied format, rather than as a table, until it's
+    /// switched with `:format`
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Page command output taller than the terminal, through `$PAGER` when it's set or
+    /// otherwise a built-in pager (space for the next page, enter for the next line, `/` to
+    /// search and `q` to quit). Paged output is shown once the command completes, and output
+    /// is never paged for scripts or when it isn't a terminal
+    pub fn with_pager(mut self) -> Self {
+        self.pager = true;
+        self
+    }
+
+    /// Set how confirmations (see [ReplCommandProcessor::requires_confirmation]) are answered
+    /// when running a script, rather than prompting a user. Defaults to declining them
+    pub fn with_script_confirmation(mut self, policy: ScriptConfirmation) -> Self {
+        self.script_confirmation = policy;
+        self
+    }
+
+    /// Add a layer to the middleware chain wrapping the dispatch of each top-level command to
+    /// the processor. The first layer added is the outermost, and runs after the
+    /// [ReplHooks::before_command] hook. See [ReplMiddleware]
+    pub fn with_middleware<M: ReplMiddleware<C> + 'static>(mut self, layer: M) -> Self {
+        self.middleware.push(Box::new(layer));
+        self
+    }
+
+    /// Set where [Repl::process_async] runs its blocking terminal reads. Defaults to
+    /// a dedicated thread per read, which works on every executor, however a runtime's
+    /// own blocking pool can be supplied instead (e.g. `tokio::task::spawn_blocking`)
+    pub fn with_blocking_spawner<S: BlockingSpawner + 'static>(mut self, spawner: S) -> Self {
+        self.spawner = Spawner::new(spawner);
+        self
+    }
+
+    /// The exit status of the last command (`$?`). 0 when it succeeded, the code of a
+    /// [crate::StatusError] it failed with, 1 for other failures and denied or cancelled
+    /// commands, and 2 for a line which wasn't a valid command. Useful as the process's exit
+    /// code once a script completes
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Retrieve a handle which prints messages above the prompt, redrawing the
+    /// prompt and any partial user input afterwards. The handle is cheap to clone
+    /// and can be sent to background tasks or threads.
+    ///
+    /// See [crate::ReplLogger] to route [log] output through it
+    pub fn printer(&mut self) -> ReplPrinter {
+        if let Some(printer) = &self.printer {
+            return printer.clone();
+        }
+
+        let external = match self.input.editor().create_external_printer() {
+            Ok(external) => Some(Box::new(external) as Box<dyn ExternalPrinter + Send>),
+            Err(err) => {
+                debug!(
+                    "External printing unavailable, falling back to stdout: {}",
+                    err
+                );
+                None
+            }
+        };
+        let printer = ReplPrinter::new(external);
+        self.printer = Some(printer.clone());
+        printer
+    }
+}
+
+impl<C, P> Repl<C, P>
+where
+    C: clap::Parser + 'static,
+{
     /// Construct a new REPL infterface.
     ///
     /// You can supply the (optional) history file for command history. Utilizing rustyline we can
     /// utilize the history for up & down arrow navigation of past commands. Having the history
     /// file be null will be no history is loaded nor stored
     ///
+    /// * `command_processor` - The processor of parsed commands, either sync or async
     /// * `history_file` - The optional command history file. Can be a full path, relative path, directory, or just the end filename to utilize
     /// * `prompt` - The prompt to display to the user to enter input. Defaults to ">>"
     pub fn new(
-        command_processor: Box<dyn crate::commands::ReplCommandProcessor<C>>,
+        command_processor: P,
         history_file: Option<String>,
         prompt: Option<String>,
     ) -> Result<Self> {
-        let history_path = Self::get_history_file_path(history_file);
-        let editor = Self::get_editor(&history_path)?;
-        Ok(Self {
-            editor,
-            history: history_path,
+        Self::build(
+            Box::new(ClapParser::<C>::new()),
             command_processor,
-            prompt: prompt.unwrap_or_else(|| "$ ".to_string()),
-            _command_type: PhantomData,
-        })
+            history_file,
+            prompt,
+        )
     }
+}
 
-    /// Execute the REPL, prompting for user input and processing the results
-    #[cfg(feature = "async")]
-    pub async fn process(&mut self) -> Result<()> {
-        process_block!(self)
+impl<C, P> Repl<C, P>
+where
+    C: clap::Subcommand + 'static,
+{
+    /// Construct a new REPL interface directly around a [clap::Subcommand] enum, without
+    /// wrapping it in a [clap::Parser] structure. Each variant is a top-level REPL command
+    /// and the parsed variant is handed straight to the processor.
+    ///
+    /// * `command_processor` - The processor of parsed commands, either sync or async
+    /// * `history_file` - The optional command history file. Can be a full path, relative path, directory, or just the end filename to utilize
+    /// * `prompt` - The prompt to display to the user to enter input. Defaults to ">>"
+    pub fn from_subcommand(
+        command_processor: P,
+        history_file: Option<String>,
+        prompt: Option<String>,
+    ) -> Result<Self> {
+        Self::build(
+            Box::new(ClapParser::<C>::from_subcommand()),
+            command_processor,
+            history_file,
+            prompt,
+        )
+    }
+}
+
+impl<P> Repl<clap::ArgMatches, P> {
+    /// Construct a new REPL interface around a [clap::Command] built at runtime (e.g. from
+    /// a configuration file or plugin discovery) rather than a [clap::Parser] structure. Each
+    /// successfully parsed line is handed to the processor as [clap::ArgMatches].
+    ///
+    /// * `command` - The command definition each line is parsed against
+    /// * `command_processor` - The processor of parsed commands, either sync or async
+    /// * `history_file` - The optional command history file. Can be a full path, relative path, directory, or just the end filename to utilize
+    /// * `prompt` - The prompt to display to the user to enter input. Defaults to ">>"
+    pub fn from_command(
+        command: clap::Command,
+        command_processor: P,
+        history_file: Option<String>,
+        prompt: Option<String>,
+    ) -> Result<Self> {
+        Self::build(
+            Box::new(ClapParser::from_command(command)),
+            command_processor,
+            history_file,
+            prompt,
+        )
     }
+}
 
+impl<C, P> Repl<C, P>
+where
+    P: ReplCommandProcessor<C>,
+{
     /// Execute the REPL, prompting for user input and processing the results
-    #[cfg(not(feature = "async"))]
     pub fn process(&mut self) -> Result<()> {
-        process_block!(self)
+        process_block!(self, sync)
+    }
+
+    /// Dispatch the command to the processor through the middleware chain, with a console
+    /// answering confirmations yes if the line bypassed them
+    fn dispatch(&mut self, command: C, bypassed: bool) -> Result<()> {
+        let console = &self.console(false).with_confirmation_bypassed(bypassed);
+        let processor = &self.command_processor;
+        let process = |command| -> Result<()> {
+            let result = processor.process_command_result(command, console)?;
+            console.render_returned(result)
+        };
+        if self.middleware.is_empty() {
+            return process(command);
+        }
+        let dispatch = |command| -> BoxFuture<'_, Result<()>> {
+            Box::pin(std::future::ready(process(command)))
+        };
+        crate::runtime::block_on(Next::new(&self.middleware, &dispatch, console).run(command))
+    }
+}
+
+impl<C, P> Repl<C, P>
+where
+    P: AsyncReplCommandProcessor<C>,
+{
+    /// Execute the REPL, prompting for user input and processing the results
+    /// with the asynchronous command processor.
+    ///
+    /// This only relies on [std] futures, so it can be driven by any executor. Blocking
+    /// terminal reads are executed by the configured [BlockingSpawner]
+    pub async fn process_async(&mut self) -> Result<()> {
+        process_block!(self, async)
+    }
+
+    /// Dispatch the command to the processor through the middleware chain, with a console
+    /// answering confirmations yes if the line bypassed them
+    async fn dispatch_async(&mut self, command: C, bypassed: bool) -> Result<()> {
+        let console = &self.console(true).with_confirmation_bypassed(bypassed);
+        let processor = &self.command_processor;
+        let process = |command| async move {
+            let result = processor.process_command_result(command, console).await?;
+            console.render_returned(result)
+        };
+        if self.middleware.is_empty() {
+            return process(command).await;
+        }
+        let dispatch = |command| -> BoxFuture<'_, Result<()>> { Box::pin(process(command)) };
+        Next::new(&self.middleware, &dispatch, console)
+            .run(command)
+            .await
     }
 }
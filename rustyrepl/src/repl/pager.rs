@@ -0,0 +1,165 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Paging of output taller than the terminal, through `$PAGER` when it's set or otherwise a
+//! built-in `more`-style pager
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use std::io::{BufRead, Write};
+use std::process::{Command, Stdio};
+
+use crate::output::OutputStream;
+
+/// The built-in pager's prompt between pages
+const PROMPT: &str = "--More-- (space: page, enter: line, /: search, n: next match, q: quit)";
+
+/// The number of terminal rows the lines take, once wrapped to the terminal's width
+fn rows(lines: &[String], columns: usize) -> usize {
+    lines
+        .iter()
+        .map(|line| line.chars().count().max(1).div_ceil(columns.max(1)))
+        .sum()
+}
+
+/// Page the lines if they're taller than the terminal, otherwise write them to the output
+pub(crate) fn page(lines: &[String], mut out: OutputStream) -> std::io::Result<()> {
+    let (columns, height) = terminal::size()?;
+    if rows(lines, columns.into()) < height.into() {
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+        return out.flush();
+    }
+    // the pager only writes to the terminal, so copy the lines to any transcript
+    let mut copies = out.copies();
+    for line in lines {
+        writeln!(copies, "{}", line)?;
+    }
+    copies.flush()?;
+    match std::env::var("PAGER") {
+        Ok(pager) if !pager.trim().is_empty() => external(&pager, lines),
+        _ => builtin(lines, columns.into(), height.into()),
+    }
+}
+
+/// Pipe the lines into the user's pager, i.e. `less -R`
+fn external(pager: &str, lines: &[String]) -> std::io::Result<()> {
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        for line in lines {
+            // the pager quitting early closes its input, which isn't an error
+            if writeln!(stdin, "{}", line).is_err() {
+                break;
+            }
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// What the user asked of the built-in pager
+enum Action {
+    Page,
+    Line,
+    Search,
+    SearchAgain,
+    Quit,
+}
+
+/// Read a key press in raw mode
+fn read_action() -> std::io::Result<Action> {
+    terminal::enable_raw_mode()?;
+    let action = loop {
+        match event::read() {
+            Ok(Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            })) => match code {
+                KeyCode::Char(' ') | KeyCode::PageDown => break Ok(Action::Page),
+                KeyCode::Enter | KeyCode::Down | KeyCode::Char('j') => break Ok(Action::Line),
+                KeyCode::Char('/') => break Ok(Action::Search),
+                KeyCode::Char('n') => break Ok(Action::SearchAgain),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Ok(Action::Quit)
+                }
+                KeyCode::Char('q') | KeyCode::Esc => break Ok(Action::Quit),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(err) => break Err(err),
+        }
+    };
+    terminal::disable_raw_mode()?;
+    action
+}
+
+/// Page through the lines a screen at a time, in the style of `more`
+fn builtin(lines: &[String], columns: usize, height: usize) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    let page = height.saturating_sub(1).max(1);
+    let mut search: Option<String> = None;
+    let mut next = 0;
+    let mut budget = page;
+    while next < lines.len() {
+        // fill the budget of rows, always showing at least one line
+        let mut used = 0;
+        while next < lines.len() {
+            let needed = rows(&lines[next..=next], columns);
+            if used > 0 && used + needed > budget {
+                break;
+            }
+            writeln!(stdout, "{}", lines[next])?;
+            used += needed;
+            next += 1;
+        }
+        if next >= lines.len() {
+            break;
+        }
+        write!(stdout, "{}", PROMPT)?;
+        stdout.flush()?;
+        let action = read_action();
+        // erase the prompt
+        write!(stdout, "\r{}\r", " ".repeat(PROMPT.len().min(columns)))?;
+        budget = match action? {
+            Action::Page => page,
+            Action::Line => 1,
+            Action::Quit => break,
+            action @ (Action::Search | Action::SearchAgain) => {
+                if let Action::Search = action {
+                    write!(stdout, "/")?;
+                    stdout.flush()?;
+                    let mut pattern = String::new();
+                    std::io::stdin().lock().read_line(&mut pattern)?;
+                    let pattern = pattern.trim_end_matches(&['\r', '\n'][..]);
+                    // an empty pattern repeats the last search
+                    if !pattern.is_empty() {
+                        search = Some(pattern.to_string());
+                    }
+                }
+                if let Some(pattern) = &search {
+                    match lines[next..].iter().position(|line| line.contains(pattern)) {
+                        Some(skipped) => {
+                            if skipped > 0 {
+                                writeln!(stdout, "...skipping")?;
+                            }
+                            next += skipped;
+                        }
+                        None => writeln!(stdout, "Pattern not found")?,
+                    }
+                }
+                page
+            }
+        };
+    }
+    stdout.flush()
+}
@@ -0,0 +1,212 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Redirection of a line's output to a file (`> out.txt`, `>> log.txt`) and through built-in
+//! filters (`| grep pattern`, `| head 20`, `| count`), without involving a shell
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::output::OutputStream;
+
+/// A built-in filter of a line's output
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    /// Lines containing the pattern, or those which don't when inverted (`grep -v`). `grep -i`
+    /// ignores case
+    Grep {
+        pattern: String,
+        invert: bool,
+        ignore_case: bool,
+    },
+    /// The first lines
+    Head(usize),
+    /// The last lines
+    Tail(usize),
+    /// The number of lines
+    Count,
+}
+
+/// The number of lines kept by `head` and `tail` when it isn't supplied
+const DEFAULT_LINES: usize = 10;
+
+impl Filter {
+    fn parse(tokens: &[&str]) -> Result<Self, String> {
+        match tokens {
+            ["grep", options @ .., pattern] => {
+                let (mut invert, mut ignore_case) = (false, false);
+                for option in options {
+                    match *option {
+                        "-v" => invert = true,
+                        "-i" => ignore_case = true,
+                        _ => return Err(format!("Unknown grep option '{}'", option)),
+                    }
+                }
+                let pattern = if ignore_case {
+                    pattern.to_lowercase()
+                } else {
+                    pattern.to_string()
+                };
+                Ok(Filter::Grep {
+                    pattern,
+                    invert,
+                    ignore_case,
+                })
+            }
+            ["head"] => Ok(Filter::Head(DEFAULT_LINES)),
+            ["tail"] => Ok(Filter::Tail(DEFAULT_LINES)),
+            ["head" | "tail", lines] => {
+                let lines = lines
+                    .trim_start_matches('-')
+                    .parse()
+                    .map_err(|_| format!("Expected a number of lines, found '{}'", lines))?;
+                Ok(match tokens[0] {
+                    "head" => Filter::Head(lines),
+                    _ => Filter::Tail(lines),
+                })
+            }
+            ["count"] => Ok(Filter::Count),
+            ["grep"] => Err("Expected a pattern to grep for".to_string()),
+            [] => Err("Expected a filter after '|'".to_string()),
+            [name, ..] => Err(format!(
+                "Unknown filter '{}', expected one of: grep, head, tail, count",
+                name
+            )),
+        }
+    }
+
+    fn apply(&self, lines: Vec<String>) -> Vec<String> {
+        match self {
+            Filter::Grep {
+                pattern,
+                invert,
+                ignore_case,
+            } => lines
+                .into_iter()
+                .filter(|line| {
+                    let found = if *ignore_case {
+                        line.to_lowercase().contains(pattern.as_str())
+                    } else {
+                        line.contains(pattern.as_str())
+                    };
+                    found != *invert
+                })
+                .collect(),
+            Filter::Head(count) => lines.into_iter().take(*count).collect(),
+            Filter::Tail(count) => {
+                let skip = lines.len().saturating_sub(*count);
+                lines.into_iter().skip(skip).collect()
+            }
+            Filter::Count => vec![lines.len().to_string()],
+        }
+    }
+}
+
+/// Where a line's output is written, rather than the REPL's output
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    /// `> file`, replacing the file's contents
+    Truncate(PathBuf),
+    /// `>> file`, appending to the file
+    Append(PathBuf),
+    /// The REPL's output, through the pager when it's taller than the terminal
+    Pager,
+}
+
+/// The filters and file a line's output passes through
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Redirection {
+    filters: Vec<Filter>,
+    target: Option<Target>,
+}
+
+/// Whether the token separates the command from its redirection, or one filter from the next
+fn is_separator(token: &str) -> bool {
+    matches!(token, "|" | ">" | ">>")
+}
+
+impl Redirection {
+    /// Split the line's tokens into the command's tokens and any trailing redirection, i.e.
+    /// `list --all | grep orders > orders.txt`. Only standalone `|`, `>` and `>>` tokens
+    /// redirect, so arguments such as `>=` are left alone
+    pub(crate) fn split<'a, 'b>(
+        tokens: &'b [&'a str],
+    ) -> (&'b [&'a str], Option<Result<Self, String>>) {
+        match tokens.iter().position(|token| is_separator(token)) {
+            Some(start) => (&tokens[..start], Some(Self::parse(&tokens[start..]))),
+            None => (tokens, None),
+        }
+    }
+
+    /// Parse the redirection, starting from its first separator
+    fn parse(mut rest: &[&str]) -> Result<Self, String> {
+        let mut redirection = Self::default();
+        while let Some((token, tail)) = rest.split_first() {
+            if redirection.target.is_some() {
+                return Err(format!("Unexpected '{}' after the output file", token));
+            }
+            if *token == "|" {
+                let length = tail.iter().take_while(|token| !is_separator(token)).count();
+                redirection.filters.push(Filter::parse(&tail[..length])?);
+                rest = &tail[length..];
+            } else {
+                let Some((path, tail)) = tail.split_first() else {
+                    return Err("Expected a file to write the output to".to_string());
+                };
+                let path = PathBuf::from(path);
+                redirection.target = Some(if *token == ">>" {
+                    Target::Append(path)
+                } else {
+                    Target::Truncate(path)
+                });
+                rest = tail;
+            }
+        }
+        Ok(redirection)
+    }
+
+    /// Whether the output is only held back to page it, rather than filtered or written to a
+    /// file
+    pub(crate) fn only_pages(&self) -> bool {
+        self.filters.is_empty() && self.target == Some(Target::Pager)
+    }
+
+    /// Whether the output is written to a file
+    pub(crate) fn writes_file(&self) -> bool {
+        matches!(self.target, Some(Target::Truncate(_) | Target::Append(_)))
+    }
+
+    /// Pass the lines through the filters
+    pub(crate) fn filter(&self, lines: Vec<String>) -> Vec<String> {
+        self.filters
+            .iter()
+            .fold(lines, |lines, filter| filter.apply(lines))
+    }
+
+    /// Page the output, unless it's written to a file
+    pub(crate) fn paged(mut self) -> Self {
+        self.target.get_or_insert(Target::Pager);
+        self
+    }
+
+    /// Pass the line's captured output through the filters, writing the result to the file,
+    /// or to the REPL's output if there's no file
+    pub(crate) fn finish(&self, captured: &str, out: OutputStream) -> std::io::Result<()> {
+        let lines = self.filter(captured.lines().map(str::to_string).collect());
+        let mut writer: Box<dyn Write> = match &self.target {
+            Some(Target::Truncate(path)) => Box::new(std::fs::File::create(path)?),
+            Some(Target::Append(path)) => {
+                Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            Some(Target::Pager) => return super::pager::page(&lines, out),
+            None => Box::new(out),
+        };
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! The results of recent commands, which later lines reference with `$_` (the last result) and
+//! `$_3` (the result of command number 3), optionally followed by a path into the result
+//! (i.e. `$_.id` or `$_3.0.name`)
+
+use serde_json::Value;
+use std::collections::VecDeque;
+
+use crate::render::cell;
+
+/// The number of results kept, after which the oldest are forgotten
+const RESULTS_KEPT: usize = 100;
+
+/// The results of recent commands, by command number
+#[derive(Debug, Default)]
+pub(crate) struct Results {
+    entries: VecDeque<(usize, Value)>,
+}
+
+/// A `$_` reference, with the command number it refers to (or the last result) and the path
+/// within the result
+struct Reference<'a> {
+    number: Option<usize>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Reference<'a> {
+    /// The reference in the token, if it's one
+    fn parse(token: &'a str) -> Option<Self> {
+        let reference = token.strip_prefix("$_")?;
+        let mut parts = reference.split('.');
+        let number = match parts.next().unwrap_or_default() {
+            "" => None,
+            number => Some(number.parse().ok()?),
+        };
+        Some(Self {
+            number,
+            path: parts.collect(),
+        })
+    }
+}
+
+impl Results {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Keep the result of the numbered command
+    pub(crate) fn push(&mut self, number: usize, value: Value) {
+        if self.entries.len() == RESULTS_KEPT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((number, value));
+    }
+
+    /// Replace each token referencing a result with the referenced value
+    pub(crate) fn expand(&self, tokens: &[&str]) -> Result<Vec<String>, String> {
+        tokens
+            .iter()
+            .map(|token| match Reference::parse(token) {
+                Some(reference) => self.resolve(token, &reference),
+                None => Ok(token.to_string()),
+            })
+            .collect()
+    }
+
+    fn resolve(&self, token: &str, reference: &Reference<'_>) -> Result<String, String> {
+        let result = match reference.number {
+            Some(number) => self
+                .entries
+                .iter()
+                .find(|(entry, _)| *entry == number)
+                .map(|(_, value)| value),
+            None => self.entries.back().map(|(_, value)| value),
+        };
+        let mut value = result.ok_or_else(|| match reference.number {
+            Some(number) => format!("Command {} has no result", number),
+            None => "There are no results yet".to_string(),
+        })?;
+        for part in &reference.path {
+            let inner = match value {
+                Value::Object(object) => object.get(*part),
+                Value::Array(values) => {
+                    part.parse().ok().and_then(|index: usize| values.get(index))
+                }
+                _ => None,
+            };
+            value = inner.ok_or_else(|| format!("'{}' isn't within the result", token))?;
+        }
+        Ok(cell(value))
+    }
+}
+
+impl std::fmt::Display for Results {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(number, value)| format!("$_{}  {}", number, value))
+            .collect();
+        f.write_str(&entries.join("\n"))
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! "Did you mean" suggestions for mistyped input, drawn from the known command names
+//! and the lines most frequently entered into the history
+
+use std::collections::HashMap;
+
+/// How many times a history entry must have been entered to be suggested
+const MIN_HISTORY_USES: usize = 2;
+
+/// The number of single character edits (insertions, deletions, substitutions or swaps of
+/// adjacent characters) to turn `a` into `b`
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distances[i][j] is the distance between the first i characters of a and j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distances[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The most edits a candidate may be from the input while still being a plausible typo
+fn max_distance(input: &str) -> usize {
+    (input.chars().count() / 3).max(1)
+}
+
+/// Suggest the closest correction of a line which failed to parse.
+///
+/// The line's first word is compared against the command names (i.e. sub-commands, their
+/// aliases and the REPL's built-in commands) keeping the remaining arguments, while the whole
+/// line is compared against history entries used at least [MIN_HISTORY_USES] times which are
+/// `valid` (i.e. parse, since failed lines are kept in the history too). The closest candidate
+/// wins, preferring commands and then the more frequent history entry on a tie
+pub(crate) fn suggest<'a, I, V>(
+    line: &str,
+    commands: &[String],
+    history: I,
+    mut valid: V,
+) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+    V: FnMut(&str) -> bool,
+{
+    let line = line.trim();
+    let word = line.split_whitespace().next()?;
+    let rest = &line[word.len()..];
+    let lowercase = word.to_lowercase();
+
+    // (distance, preference, suggestion) where a lower preference wins ties
+    let mut best: Option<(usize, usize, String)> = None;
+    let mut consider = |distance: usize, preference: usize, suggestion: String| {
+        if best
+            .as_ref()
+            .map_or(true, |(d, p, _)| (distance, preference) < (*d, *p))
+        {
+            best = Some((distance, preference, suggestion));
+        }
+    };
+
+    if !commands.iter().any(|command| command == word) {
+        for command in commands {
+            let distance = edit_distance(&lowercase, &command.to_lowercase());
+            if distance <= max_distance(word) {
+                consider(distance, 0, format!("{}{}", command, rest));
+            }
+        }
+    }
+
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for entry in history {
+        *uses.entry(entry.trim()).or_default() += 1;
+    }
+    for (entry, count) in uses {
+        if count < MIN_HISTORY_USES || entry == line {
+            continue;
+        }
+        let distance = edit_distance(line, entry);
+        if distance <= max_distance(line) && valid(entry) {
+            consider(distance, usize::MAX - count, entry.to_string());
+        }
+    }
+
+    best.map(|(_, _, suggestion)| suggestion)
+}
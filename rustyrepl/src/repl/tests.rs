@@ -4,10 +4,18 @@
 // LICENSE file in the root directory of this source tree.
 
 use super::*;
+use crate::{
+    AuditMiddleware, ConfirmMiddleware, OutputBuffer, OutputFormat, Render, ReplOutput,
+    ScriptConfirmation, StatusError, TimingMiddleware,
+};
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::cell::Cell;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Parser, Debug)]
 struct TestCli {}
@@ -42,7 +50,7 @@ fn test_history_path_parsing() -> Result<()> {
     );
 
     // ========= A directory ========= //
-    let mut tempdir = tempfile::tempdir()?.into_path();
+    let mut tempdir = tempfile::tempdir()?.keep();
     let directory_plus_default_filename =
         TestRepl::get_history_file_path(Some(tempdir.to_str().unwrap().to_string()));
     tempdir.push(super::DEFAULT_HISTORY_FILE_NAME);
@@ -55,3 +63,1997 @@ fn test_history_path_parsing() -> Result<()> {
 
     Ok(())
 }
+
+#[derive(Debug)]
+struct TestProcessor {}
+
+impl ReplCommandProcessor<TestCli> for TestProcessor {
+    fn process_command(&self, _command: TestCli) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+impl AsyncReplCommandProcessor<TestCli> for TestProcessor {
+    async fn process_command(&self, _command: TestCli) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_processor_kinds() -> Result<()> {
+    // A bare processor, or a boxed one, can drive the synchronous loop
+    let _sync: Repl<TestCli, TestProcessor> = Repl::new(TestProcessor {}, None, None)?;
+    let mut boxed = TestRepl::new(Box::new(TestProcessor {}), None, None)?;
+    boxed = boxed.with_script("quit".as_bytes());
+    boxed.process()?;
+
+    // while the same type can also drive the async loop, boxed or not
+    let mut repl: Repl<TestCli, Box<TestProcessor>> =
+        Repl::new(Box::new(TestProcessor {}), None, None)?.with_script("quit".as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    Ok(())
+}
+
+#[test]
+fn test_printer_is_shareable() -> Result<()> {
+    fn assert_send_clone<T: Send + Clone + 'static>(_: &T) {}
+
+    let mut repl = TestRepl::new(Box::new(TestProcessor {}), None, None)?;
+    let printer = repl.printer();
+    assert_send_clone(&printer);
+
+    // Printing from another thread shouldn't fail, whether or not we're attached to a TTY
+    let cloned = printer.clone();
+    std::thread::spawn(move || cloned.print("from a background thread"))
+        .join()
+        .expect("Printer thread panicked")?;
+    printer.print("from the main thread\n")?;
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct CountCli {
+    #[clap(subcommand)]
+    command: CountCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CountCommand {
+    /// Increment the counter
+    Count,
+}
+
+#[derive(Debug, Default, Clone)]
+struct CountingProcessor {
+    count: Arc<AtomicUsize>,
+}
+
+impl ReplCommandProcessor<CountCli> for CountingProcessor {
+    fn process_command(&self, command: CountCli) -> Result<()> {
+        match command.command {
+            CountCommand::Count => self.count.fetch_add(1, Ordering::SeqCst),
+        };
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+impl AsyncReplCommandProcessor<CountCli> for CountingProcessor {
+    async fn process_command(&self, command: CountCli) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<CountCli>::is_quit(self, command)
+    }
+}
+
+/// Two valid commands, some noise, then everything after "quit" is ignored
+const COUNTING_SCRIPT: &str = "count\n\nnot-a-command\ncount\nquit\ncount\n";
+
+fn counting_repl() -> Result<(Repl<CountCli, CountingProcessor>, Arc<AtomicUsize>)> {
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let repl = Repl::new(processor, None, None)?.with_script(COUNTING_SCRIPT.as_bytes());
+    Ok((repl, count))
+}
+
+#[test]
+fn test_script_sync() -> Result<()> {
+    let (mut repl, count) = counting_repl()?;
+    repl.process()?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_tokio() -> Result<()> {
+    let (repl, count) = counting_repl()?;
+    let mut repl = repl.with_blocking_spawner(|job| {
+        tokio::task::spawn_blocking(job);
+    });
+    repl.process_async().await?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_async_async_std() -> Result<()> {
+    let (repl, count) = counting_repl()?;
+    let mut repl = repl.with_blocking_spawner(|job| {
+        async_std::task::spawn_blocking(job);
+    });
+    async_std::task::block_on(repl.process_async())?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_async_smol() -> Result<()> {
+    let (repl, count) = counting_repl()?;
+    let mut repl = repl.with_blocking_spawner(|job| smol::unblock(job).detach());
+    smol::block_on(repl.process_async())?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_async_futures_executor() -> Result<()> {
+    // the default thread spawner needs no runtime at all
+    let (mut repl, count) = counting_repl()?;
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_async_dropped_job_errors() -> Result<()> {
+    // a spawner which never runs the job shouldn't hang the REPL
+    let (repl, _count) = counting_repl()?;
+    let mut repl = repl.with_blocking_spawner(|job: crate::BlockingJob| drop(job));
+    assert!(futures::executor::block_on(repl.process_async()).is_err());
+    Ok(())
+}
+
+/// A processor holding `!Send` state, which is only usable on a local executor
+#[derive(Debug, Default)]
+struct LocalProcessor {
+    count: Rc<Cell<usize>>,
+}
+
+impl AsyncReplCommandProcessor<CountCli> for LocalProcessor {
+    async fn process_command(&self, command: CountCli) -> Result<()> {
+        let count = self.count.clone();
+        // hold the `Rc` across an await point
+        futures::future::ready(()).await;
+        match command.command {
+            CountCommand::Count => count.set(count.get() + 1),
+        }
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_async_local_executor() -> Result<()> {
+    let processor = LocalProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::new(processor, None, None)?.with_script(COUNTING_SCRIPT.as_bytes());
+
+    let mut pool = futures::executor::LocalPool::new();
+    pool.run_until(repl.process_async())?;
+    assert_eq!(2, count.get());
+    Ok(())
+}
+
+/// Treats any bare number which isn't a command as an amount to add to the counter
+#[derive(Debug, Default, Clone)]
+struct SummingProcessor {
+    counter: CountingProcessor,
+    declined: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl SummingProcessor {
+    fn sum(&self, line: &str) -> bool {
+        match line.trim().parse::<usize>() {
+            Ok(amount) => {
+                self.counter.count.fetch_add(amount, Ordering::SeqCst);
+                true
+            }
+            Err(_) => {
+                self.declined.lock().unwrap().push(line.to_string());
+                false
+            }
+        }
+    }
+}
+
+impl ReplCommandProcessor<CountCli> for SummingProcessor {
+    fn process_command(&self, command: CountCli) -> Result<()> {
+        ReplCommandProcessor::process_command(&self.counter, command)
+    }
+
+    fn process_unparsed(&self, line: &str) -> Result<bool> {
+        Ok(self.sum(line))
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+impl AsyncReplCommandProcessor<CountCli> for SummingProcessor {
+    async fn process_command(&self, command: CountCli) -> Result<()> {
+        ReplCommandProcessor::process_command(&self.counter, command)
+    }
+
+    async fn process_unparsed(&self, line: &str) -> Result<bool> {
+        Ok(self.sum(line))
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_unparsed_fallback() -> Result<()> {
+    const SCRIPT: &str = "count\n10\nnot-a-command\nhelp\n5\n";
+
+    let processor = SummingProcessor::default();
+    let (count, declined) = (processor.counter.count.clone(), processor.declined.clone());
+    let mut repl = Repl::new(processor.clone(), None, None)?.with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(16, count.load(Ordering::SeqCst));
+    // valid commands and help never reach the fallback, only the declined line does
+    assert_eq!(vec!["not-a-command".to_string()], *declined.lock().unwrap());
+
+    let mut repl = Repl::new(processor, None, None)?.with_script(SCRIPT.as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(32, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_unparsed_lines_never_redirect() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("b");
+    let lines = vec![
+        "select * from t where a >= 5".to_string(),
+        format!("a > {}", file.display()),
+        "a | b".to_string(),
+    ];
+
+    let processor = SummingProcessor::default();
+    let declined = processor.declined.clone();
+    let mut repl =
+        Repl::new(processor, None, None)?.with_script(std::io::Cursor::new(lines.join("\n")));
+    repl.process()?;
+    // the whole lines reach the fallback, and nothing is written
+    assert_eq!(lines, *declined.lock().unwrap());
+    assert!(!file.exists());
+    assert!(!Path::new("=").exists());
+    Ok(())
+}
+
+/// Records the values of the `count` subcommand's argument, built at runtime
+#[derive(Debug, Default, Clone)]
+struct MatchesProcessor {
+    seen: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl ReplCommandProcessor<clap::ArgMatches> for MatchesProcessor {
+    fn process_command(&self, command: clap::ArgMatches) -> Result<()> {
+        if let Some(("count", sub_matches)) = command.subcommand() {
+            let value = sub_matches
+                .get_one::<String>("value")
+                .cloned()
+                .unwrap_or_default();
+            self.seen.lock().unwrap().push(value);
+        }
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+fn runtime_command() -> clap::Command {
+    clap::Command::new("dynamic")
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("count")
+                .about("Count a value")
+                .arg(clap::Arg::new("value").required(true)),
+        )
+}
+
+#[test]
+fn test_builder_command() -> Result<()> {
+    let processor = MatchesProcessor::default();
+    let seen = processor.seen.clone();
+    let mut repl = Repl::from_command(runtime_command(), processor, None, None)?
+        .with_script("count 1\nhelp\ncount\nunknown\ncount 2\n".as_bytes());
+    repl.process()?;
+    assert_eq!(
+        vec!["1".to_string(), "2".to_string()],
+        *seen.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_builder_matches_derive_parsing() {
+    // the same line parses identically through either construction path
+    let mut derived = ClapParser::<CountCli>::new();
+    let mut built = ClapParser::from_command(<CountCli as clap::CommandFactory>::command());
+    assert!(matches!(
+        derived.parse(&["count"]),
+        ParseOutcome::Command(_)
+    ));
+    assert!(matches!(built.parse(&["count"]), ParseOutcome::Command(_)));
+    match (derived.parse(&["nope"]), built.parse(&["nope"])) {
+        (ParseOutcome::Error(derived), ParseOutcome::Error(built)) => assert_eq!(derived, built),
+        _ => panic!("Expected both to fail parsing"),
+    }
+    assert!(matches!(derived.parse(&["help"]), ParseOutcome::Help(_)));
+    assert!(matches!(built.parse(&["help"]), ParseOutcome::Help(_)));
+}
+
+impl ReplCommandProcessor<CountCommand> for CountingProcessor {
+    fn process_command(&self, command: CountCommand) -> Result<()> {
+        match command {
+            CountCommand::Count => self.count.fetch_add(1, Ordering::SeqCst),
+        };
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_subcommand_enum() -> Result<()> {
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::<CountCommand, _>::from_subcommand(processor, None, None)?
+        .with_script(COUNTING_SCRIPT.as_bytes());
+
+    // the variants are listed as top-level commands in the help
+    match ClapParser::<CountCommand>::from_subcommand().parse(&["help"]) {
+        ParseOutcome::Help(help) => {
+            assert!(help.contains("count"), "{}", help);
+            assert!(help.contains("Increment the counter"), "{}", help);
+        }
+        _ => panic!("Expected the help menu"),
+    }
+
+    repl.process()?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+/// A hand-written parser of "count <n>" into the number of times to count
+#[derive(Debug)]
+struct TimesParser;
+
+impl CommandParser<usize> for TimesParser {
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<usize> {
+        match tokens {
+            ["count", times] => times
+                .parse()
+                .map(ParseOutcome::Command)
+                .unwrap_or_else(|err| ParseOutcome::Error(format!("Invalid count: {}", err))),
+            ["help"] => ParseOutcome::Help("count <n>".to_string()),
+            _ => ParseOutcome::Error("Unknown command".to_string()),
+        }
+    }
+}
+
+impl ReplCommandProcessor<usize> for CountingProcessor {
+    fn process_command(&self, command: usize) -> Result<()> {
+        self.count.fetch_add(command, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_custom_parser() -> Result<()> {
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_script("count 3\nhelp\ncount x\ncount   4\n".as_bytes());
+    repl.process()?;
+    assert_eq!(7, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+/// Deploy an application
+#[derive(Parser, Debug)]
+#[clap(name = "deployer")]
+struct DeployCli {
+    #[clap(subcommand)]
+    command: DeployCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum DeployCommand {
+    /// Deploy to a target
+    Deploy {
+        /// Where to deploy to
+        target: String,
+        #[clap(subcommand)]
+        stage: Option<DeployStage>,
+    },
+    /// Describe a target
+    #[clap(alias = "show")]
+    Describe {
+        /// The output format
+        #[clap(long)]
+        format: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DeployStage {
+    /// Only run the canary
+    Canary,
+}
+
+fn help_text<C>(parser: &mut ClapParser<C>, tokens: &[&str]) -> String {
+    match parser.parse(tokens) {
+        ParseOutcome::Help(help) | ParseOutcome::Error(help) => help,
+        ParseOutcome::Command(_) => panic!("Expected help or error output for {:?}", tokens),
+    }
+}
+
+#[test]
+fn test_usage_without_binary_name() {
+    let mut parser = ClapParser::<DeployCli>::new();
+    assert!(help_text(&mut parser, &["help"]).contains("Usage: <COMMAND>"));
+    assert!(help_text(&mut parser, &["help", "deploy"]).contains("Usage: deploy <TARGET>"));
+    assert!(help_text(&mut parser, &["deploy", "--help"]).contains("Usage: deploy <TARGET>"));
+    assert!(help_text(&mut parser, &["help", "deploy", "canary"])
+        .contains("Usage: deploy <TARGET> canary"));
+    assert!(help_text(&mut parser, &["deploy"]).contains("Usage: deploy <TARGET>"));
+
+    let mut parser = ClapParser::<DeployCommand>::from_subcommand();
+    assert!(help_text(&mut parser, &["help"]).contains("Usage: <COMMAND>"));
+    assert!(help_text(&mut parser, &["help", "deploy"]).contains("Usage: deploy <TARGET>"));
+}
+
+#[test]
+fn test_usage_with_app_name() {
+    let mut parser = ClapParser::<DeployCli>::new().with_app_name();
+    assert!(help_text(&mut parser, &["help"]).contains("Usage: deployer <COMMAND>"));
+    assert!(help_text(&mut parser, &["help", "deploy"]).contains("Usage: deployer deploy <TARGET>"));
+    assert!(help_text(&mut parser, &["help", "deploy", "canary"])
+        .contains("Usage: deployer deploy <TARGET> canary"));
+}
+
+fn parse_deploy(parser: &mut ClapParser<DeployCli>, tokens: &[&str]) -> DeployCommand {
+    match parser.parse(tokens) {
+        ParseOutcome::Command(cli) => cli.command,
+        other => panic!("Expected {:?} to parse, got {:?}", tokens, other),
+    }
+}
+
+#[test]
+fn test_prefix_matching() {
+    // exact names are required by default
+    let mut parser = ClapParser::<DeployCli>::new();
+    assert!(matches!(
+        parser.parse(&["dep", "prod"]),
+        ParseOutcome::Error(_)
+    ));
+
+    let mut parser = ClapParser::<DeployCli>::new().with_prefix_matching();
+    assert!(matches!(
+        parse_deploy(&mut parser, &["dep", "prod"]),
+        DeployCommand::Deploy { target, stage: None } if target == "prod"
+    ));
+    assert!(matches!(
+        parse_deploy(&mut parser, &["DePloy", "prod", "CAN"]),
+        DeployCommand::Deploy {
+            stage: Some(DeployStage::Canary),
+            ..
+        }
+    ));
+    // aliases resolve to their sub-command, and option values are never rewritten
+    assert!(matches!(
+        parse_deploy(&mut parser, &["sh", "--format", "de"]),
+        DeployCommand::Describe { format: Some(format) } if format == "de"
+    ));
+    // the required target is filled before sub-commands are resolved
+    assert!(matches!(
+        parse_deploy(&mut parser, &["deploy", "c"]),
+        DeployCommand::Deploy { target, stage: None } if target == "c"
+    ));
+    assert!(matches!(
+        parse_deploy(&mut parser, &["deploy", "h"]),
+        DeployCommand::Deploy { target, stage: None } if target == "h"
+    ));
+    assert!(matches!(
+        parse_deploy(&mut parser, &["dep", "c", "c"]),
+        DeployCommand::Deploy { target, stage: Some(DeployStage::Canary) } if target == "c"
+    ));
+    match parser.parse(&["help", "desc"]) {
+        ParseOutcome::Help(help) => assert!(help.contains("Usage: describe"), "{}", help),
+        other => panic!("Expected the describe help, got {:?}", other),
+    }
+
+    match parser.parse(&["De", "prod"]) {
+        ParseOutcome::Error(err) => {
+            assert!(err.contains("'De' is ambiguous"), "{}", err);
+            assert!(err.contains("deploy, describe"), "{}", err);
+        }
+        other => panic!("Expected an ambiguous prefix error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(0, suggest::edit_distance("deploy", "deploy"));
+    assert_eq!(1, suggest::edit_distance("delpoy", "deploy"));
+    assert_eq!(2, suggest::edit_distance("edpoly", "deploy"));
+    assert_eq!(1, suggest::edit_distance("deply", "deploy"));
+    assert_eq!(6, suggest::edit_distance("", "deploy"));
+}
+
+#[test]
+fn test_suggestions() {
+    let commands: Vec<String> = ["deploy", "describe", "show", "help"]
+        .iter()
+        .map(|command| command.to_string())
+        .collect();
+
+    // typos of commands and aliases keep their arguments
+    assert_eq!(
+        Some("deploy prod canary".to_string()),
+        suggest::suggest("delpoy prod canary", &commands, [], |_| true)
+    );
+    assert_eq!(
+        Some("show".to_string()),
+        suggest::suggest("SHWO", &commands, [], |_| true)
+    );
+    assert_eq!(
+        Some("help".to_string()),
+        suggest::suggest("hepl", &commands, [], |_| true)
+    );
+    assert_eq!(
+        None,
+        suggest::suggest("frobnicate", &commands, [], |_| true)
+    );
+
+    // frequently used history is suggested as a whole line, while one-offs aren't
+    let history = [
+        "select * from orders",
+        "x",
+        "select * from orders",
+        "selcet 1",
+    ];
+    assert_eq!(
+        Some("select * from orders".to_string()),
+        suggest::suggest("select * from ordres", &commands, history, |_| true)
+    );
+    assert_eq!(
+        None,
+        suggest::suggest("selcet 2", &commands, history, |_| true)
+    );
+
+    // as long as it's valid
+    assert_eq!(
+        None,
+        suggest::suggest("select * from ordres", &commands, history, |entry| {
+            entry != "select * from orders"
+        })
+    );
+}
+
+#[test]
+fn test_suggestion_prompt() -> Result<()> {
+    // the first typo is declined, the second's suggestion is run
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::<CountCli, _>::new(processor, None, None)?
+        .with_suggestion_prompt()
+        .with_script("cuont\nn\ncuont\ny\nCOUNT\nyes\n".as_bytes());
+    repl.process()?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_invalid_history_isnt_suggested() -> Result<()> {
+    let mut repl = Repl::<CountCli, _>::new(CountingProcessor::default(), None, None)?;
+    for line in ["count --dry", "count", "count --dry", "count"] {
+        let _ = repl.input.editor().add_history_entry(line);
+    }
+    assert_eq!(None, repl.suggest("count --dyr"));
+    assert_eq!(Some("count".to_string()), repl.suggest("count x"));
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct EnvCli {
+    /// The environment to run against
+    #[clap(long, global = true)]
+    env: Option<String>,
+    #[clap(subcommand)]
+    command: CountCommand,
+}
+
+/// Records the environment each command ran against
+#[derive(Debug, Default, Clone)]
+struct EnvProcessor {
+    envs: Arc<std::sync::Mutex<Vec<Option<String>>>>,
+}
+
+impl ReplCommandProcessor<EnvCli> for EnvProcessor {
+    fn process_command(&self, command: EnvCli) -> Result<()> {
+        self.envs.lock().unwrap().push(command.env);
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_pins() {
+    let mut pins = Pins::default();
+    assert!(pins.pin(&["prod"]).is_err());
+    pins.pin(&["--env", "staging", "--verbose"]).unwrap();
+    pins.pin(&["--env=prod"]).unwrap();
+    assert_eq!("--env=prod --verbose", pins.to_string());
+    assert_eq!(
+        vec!["--env=prod", "--verbose", "count"],
+        pins.apply(&["count"])
+    );
+    // the line's own value wins, in either form
+    assert_eq!(
+        vec!["--verbose", "count", "--env", "dev"],
+        pins.apply(&["count", "--env", "dev"])
+    );
+    assert_eq!(vec!["--verbose", "--env=dev"], pins.apply(&["--env=dev"]));
+
+    pins.unpin(&["--verbose"]).unwrap();
+    assert_eq!("--env=prod", pins.to_string());
+    assert!(pins.unpin(&["env"]).is_err());
+    assert!(pins.unpin(&["--verbose"]).is_err());
+    pins.unpin(&[]).unwrap();
+    assert!(pins.is_empty());
+}
+
+#[test]
+fn test_pinned_arguments() -> Result<()> {
+    let processor = EnvProcessor::default();
+    let envs = processor.envs.clone();
+    let mut repl = Repl::<EnvCli, _>::new(processor, None, Some("$ ".to_string()))?
+        .with_script(":pin --env prod\ncount\ncount --env dev\n:unpin\ncount\n".as_bytes());
+    assert_eq!("$ ", repl.current_prompt());
+    repl.pins.pin(&["--env", "prod"]).unwrap();
+    assert_eq!("[--env prod] $ ", repl.current_prompt());
+    repl.pins.unpin(&[]).unwrap();
+
+    repl.process()?;
+    assert_eq!(
+        vec![Some("prod".to_string()), Some("dev".to_string()), None],
+        *envs.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pins_are_checked() -> Result<()> {
+    // only the parser's global arguments can be pinned
+    let mut repl = Repl::<EnvCli, _>::new(EnvProcessor::default(), None, None)?;
+    assert!(repl.pin(&["--region", "us"]).is_err());
+    assert!(repl.pin(&["--env"]).is_err());
+    repl.pin(&["--env", "prod"]).unwrap();
+    assert_eq!("--env prod", repl.pins.to_string());
+
+    // a rejected pin doesn't break the lines after it
+    let processor = DescribeProcessor::default();
+    let formats = processor.formats.clone();
+    let mut repl = Repl::<DeployCli, _>::new(processor, None, None)?
+        .with_script(":set +e\n:pin --region us\ndescribe\n".as_bytes());
+    repl.process()?;
+    assert!(repl.pins.is_empty());
+    assert_eq!(
+        (0, vec![None]),
+        (repl.status(), formats.lock().unwrap().clone())
+    );
+    Ok(())
+}
+
+/// The tokens as a parser which doesn't resolve sub-commands would return them
+fn resolved(tokens: &[&str]) -> Vec<String> {
+    tokens.iter().map(|token| token.to_string()).collect()
+}
+
+#[test]
+fn test_defaults() {
+    let mut defaults = Defaults::default();
+    assert!(defaults.set(&["--region", "us-east-1"]).is_err());
+    assert!(defaults.set(&["deploy"]).is_err());
+    defaults
+        .set(&["deploy", "--region", "us-east-1", "--dry-run"])
+        .unwrap();
+    defaults
+        .set(&["deploy", "canary", "--percent", "5"])
+        .unwrap();
+    defaults.set(&["deploy", "--region", "eu-west-1"]).unwrap();
+    assert_eq!(
+        "deploy --region eu-west-1 --dry-run\ndeploy canary --percent 5",
+        defaults.to_string()
+    );
+
+    assert_eq!(
+        vec!["deploy", "--region", "eu-west-1", "--dry-run", "prod"],
+        defaults.apply(&["deploy", "prod"], &resolved(&["deploy", "prod"]))
+    );
+    assert_eq!(
+        vec!["deploy", "--dry-run", "prod", "--region=us-west-2"],
+        defaults.apply(
+            &["deploy", "prod", "--region=us-west-2"],
+            &resolved(&["deploy", "prod", "--region=us-west-2"])
+        )
+    );
+    // the most specific sub-command's defaults apply
+    assert_eq!(
+        vec!["deploy", "canary", "--percent", "5"],
+        defaults.apply(&["deploy", "canary"], &resolved(&["deploy", "canary"]))
+    );
+    assert_eq!(
+        vec!["describe"],
+        defaults.apply(&["describe"], &resolved(&["describe"]))
+    );
+
+    defaults.clear(&["deploy", "--dry-run"]).unwrap();
+    assert_eq!(
+        "deploy --region eu-west-1\ndeploy canary --percent 5",
+        defaults.to_string()
+    );
+    defaults.clear(&["deploy", "canary"]).unwrap();
+    assert_eq!("deploy --region eu-west-1", defaults.to_string());
+    assert!(defaults.clear(&["describe"]).is_err());
+    assert!(defaults.clear(&["--region"]).is_err());
+    defaults.clear(&[]).unwrap();
+    assert!(defaults.is_empty());
+}
+
+/// Records the format of each describe command
+#[derive(Debug, Default, Clone)]
+struct DescribeProcessor {
+    formats: Arc<std::sync::Mutex<Vec<Option<String>>>>,
+}
+
+impl ReplCommandProcessor<DeployCli> for DescribeProcessor {
+    fn process_command(&self, command: DeployCli) -> Result<()> {
+        if let DeployCommand::Describe { format } = command.command {
+            self.formats.lock().unwrap().push(format);
+        }
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_saved_defaults() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let history = dir.path().join("history.txt");
+    std::fs::write(&history, "")?;
+    let history_file = Some(history.to_str().unwrap().to_string());
+
+    let processor = DescribeProcessor::default();
+    let formats = processor.formats.clone();
+    let mut repl = Repl::<DeployCli, _>::new(processor.clone(), history_file.clone(), None)?
+        .with_saved_defaults()
+        .with_script(
+            ":default describe --format json\ndescribe\ndescribe --format yaml\n".as_bytes(),
+        );
+    repl.process()?;
+    assert_eq!(
+        "describe --format json\n",
+        std::fs::read_to_string(dir.path().join("history.txt.defaults"))?
+    );
+
+    // the next session picks up where the last left off, until the default is cleared
+    let mut repl = Repl::<DeployCli, _>::new(processor, history_file, None)?
+        .with_saved_defaults()
+        .with_script("describe\n:undefault describe\ndescribe\n".as_bytes());
+    repl.process()?;
+    assert_eq!(
+        vec![
+            Some("json".to_string()),
+            Some("yaml".to_string()),
+            Some("json".to_string()),
+            None
+        ],
+        *formats.lock().unwrap()
+    );
+    assert_eq!(
+        "",
+        std::fs::read_to_string(dir.path().join("history.txt.defaults"))?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_defaults_follow_aliases_and_prefixes() -> Result<()> {
+    const SCRIPT: &str = ":default describe --format json\nshow\nDESC\ndescribe\n";
+
+    // the defaults apply however the sub-command is named
+    let processor = DescribeProcessor::default();
+    let formats = processor.formats.clone();
+    let parser = ClapParser::<DeployCli>::new().with_prefix_matching();
+    let mut repl = Repl::from_parser(parser, processor, None, None)?.with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(vec![Some("json".to_string()); 3], *formats.lock().unwrap());
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// List the tables
+    Tables,
+    /// Use a table
+    Use { table: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum TableCommand {
+    /// Count the rows of the table
+    Count,
+    /// Use another table from within this one
+    Use { table: String },
+}
+
+/// Records which table each command ran against, entering a context per table
+#[derive(Debug, Default, Clone)]
+struct DbProcessor {
+    tables: Arc<AtomicUsize>,
+    counted: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl DbProcessor {
+    fn table_context(&self, table: &str) -> ReplContext {
+        let processor = TableProcessor {
+            table: table.to_string(),
+            db: self.clone(),
+        };
+        ReplContext::new(table, ClapParser::from_subcommand(), processor)
+    }
+}
+
+impl ReplCommandProcessor<DbCommand> for DbProcessor {
+    fn process_command(&self, command: DbCommand) -> Result<()> {
+        match command {
+            DbCommand::Tables => self.tables.fetch_add(1, Ordering::SeqCst),
+            DbCommand::Use { .. } => panic!("Entering a context shouldn't be processed"),
+        };
+        Ok(())
+    }
+
+    fn enter_context(&self, command: &DbCommand) -> Option<ReplContext> {
+        match command {
+            DbCommand::Use { table } => Some(self.table_context(table)),
+            _ => None,
+        }
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[derive(Debug)]
+struct TableProcessor {
+    table: String,
+    db: DbProcessor,
+}
+
+impl ReplCommandProcessor<TableCommand> for TableProcessor {
+    fn process_command(&self, command: TableCommand) -> Result<()> {
+        if let TableCommand::Count = command {
+            self.db.counted.lock().unwrap().push(self.table.clone());
+        }
+        Ok(())
+    }
+
+    fn enter_context(&self, command: &TableCommand) -> Option<ReplContext> {
+        match command {
+            TableCommand::Use { table } => Some(self.db.table_context(table)),
+            _ => None,
+        }
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+impl AsyncReplCommandProcessor<TableCommand> for TableProcessor {
+    async fn process_command(&self, command: TableCommand) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    fn enter_context(&self, command: &TableCommand) -> Option<ReplContext> {
+        ReplCommandProcessor::enter_context(self, command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<TableCommand>::is_quit(self, command)
+    }
+}
+
+impl AsyncReplCommandProcessor<DbCommand> for DbProcessor {
+    async fn process_command(&self, command: DbCommand) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    fn enter_context(&self, command: &DbCommand) -> Option<ReplContext> {
+        match command {
+            DbCommand::Use { table } => {
+                let processor = TableProcessor {
+                    table: table.clone(),
+                    db: self.clone(),
+                };
+                Some(ReplContext::new_async(
+                    table.as_str(),
+                    ClapParser::from_subcommand(),
+                    processor,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<DbCommand>::is_quit(self, command)
+    }
+}
+
+/// Tables at the top level, then counts in nested contexts which are left one at a time,
+/// all at once, and finally the top level itself
+const CONTEXT_SCRIPT: &str = "tables\nuse orders\ncount\ntables\nuse items\ncount\nexit\ncount\n\
+                              end\ntables\nuse items\nCOUNT\ncount\nexit\nexit\ntables\n";
+
+#[test]
+fn test_nested_contexts() -> Result<()> {
+    let processor = DbProcessor::default();
+    let (tables, counted) = (processor.tables.clone(), processor.counted.clone());
+    let mut repl = Repl::<DbCommand, _>::from_subcommand(processor, None, None)?
+        .with_script(CONTEXT_SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(2, tables.load(Ordering::SeqCst));
+    assert_eq!(
+        vec!["orders", "items", "orders", "items"],
+        *counted.lock().unwrap()
+    );
+    assert!(repl.contexts.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_async_contexts() -> Result<()> {
+    let processor = DbProcessor::default();
+    let (tables, counted) = (processor.tables.clone(), processor.counted.clone());
+    let mut repl = Repl::<DbCommand, _>::from_subcommand(processor.clone(), None, None)?
+        .with_script(CONTEXT_SCRIPT.as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(2, tables.load(Ordering::SeqCst));
+    assert_eq!(
+        vec!["orders", "items", "orders", "items"],
+        *counted.lock().unwrap()
+    );
+
+    // while the synchronous loop refuses to enter them
+    let mut repl = Repl::<DbCommand, _>::from_subcommand(processor, None, None)?;
+    let context = AsyncReplCommandProcessor::enter_context(
+        &repl.command_processor,
+        &DbCommand::Use {
+            table: "orders".to_string(),
+        },
+    );
+    repl.push_context(context.unwrap(), false);
+    assert!(repl.contexts.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_context_history_and_prompt() -> Result<()> {
+    let processor = DbProcessor::default();
+    let mut repl =
+        Repl::<DbCommand, _>::from_subcommand(processor.clone(), None, Some("db> ".to_string()))?;
+    let history = |repl: &Repl<DbCommand, DbProcessor>| -> Vec<String> {
+        repl.input.editor().history().iter().cloned().collect()
+    };
+    let _ = repl.input.editor().add_history_entry("use orders");
+
+    repl.push_context(processor.table_context("orders"), false);
+    assert_eq!("orders> ", repl.current_prompt());
+    assert!(history(&repl).is_empty());
+    let _ = repl.input.editor().add_history_entry("count");
+
+    repl.pop_context();
+    assert_eq!("db> ", repl.current_prompt());
+    assert_eq!(vec!["use orders"], history(&repl));
+
+    // re-entering the context later in the session restores its history
+    repl.push_context(
+        processor.table_context("orders").with_prompt("(orders) "),
+        false,
+    );
+    assert_eq!("(orders) ", repl.current_prompt());
+    assert_eq!(vec!["count"], history(&repl));
+    Ok(())
+}
+
+/// Records each hook run, optionally denying every command
+#[derive(Debug, Default, Clone)]
+struct RecordingHooks {
+    events: Arc<std::sync::Mutex<Vec<String>>>,
+    deny: bool,
+}
+
+impl RecordingHooks {
+    fn record(&self, event: &str) {
+        self.events.lock().unwrap().push(event.to_string());
+    }
+}
+
+impl ReplHooks<CountCli> for RecordingHooks {
+    fn on_start(&self) -> Result<()> {
+        self.record("start");
+        Ok(())
+    }
+
+    fn before_command(&self, command: &CountCli) -> Result<()> {
+        self.record(&format!("before {:?}", command.command));
+        if self.deny {
+            anyhow::bail!("Counting isn't allowed");
+        }
+        Ok(())
+    }
+
+    fn after_command(&self, result: &Result<()>, duration: std::time::Duration) {
+        assert!(duration < std::time::Duration::from_secs(60));
+        self.record(if result.is_ok() {
+            "after ok"
+        } else {
+            "after err"
+        });
+    }
+
+    fn on_exit(&self) -> Result<()> {
+        self.record("exit");
+        Ok(())
+    }
+}
+
+/// Fails every command it processes
+#[derive(Debug)]
+struct FailingProcessor;
+
+impl ReplCommandProcessor<CountCli> for FailingProcessor {
+    fn process_command(&self, _command: CountCli) -> Result<()> {
+        anyhow::bail!("Counting failed")
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_hooks() -> Result<()> {
+    let (repl, count) = counting_repl()?;
+    let hooks = RecordingHooks::default();
+    let mut repl = repl.with_hooks(hooks.clone());
+    repl.process()?;
+    assert_eq!(2, count.load(Ordering::SeqCst));
+    assert_eq!(
+        vec![
+            "start",
+            "before Count",
+            "after ok",
+            "before Count",
+            "after ok",
+            "exit"
+        ],
+        *hooks.events.lock().unwrap()
+    );
+
+    // denied commands aren't processed
+    let (repl, count) = counting_repl()?;
+    let hooks = RecordingHooks {
+        deny: true,
+        ..Default::default()
+    };
+    let mut repl = repl.with_hooks(hooks.clone());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(0, count.load(Ordering::SeqCst));
+    assert_eq!(
+        vec!["start", "before Count", "before Count", "exit"],
+        *hooks.events.lock().unwrap()
+    );
+
+    // and a failing command still ends the session, though the exit hook runs
+    let hooks = RecordingHooks::default();
+    let mut repl = Repl::<CountCli, _>::new(FailingProcessor, None, None)?
+        .with_hooks(hooks.clone())
+        .with_script(COUNTING_SCRIPT.as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!(
+        vec!["start", "before Count", "after err", "exit"],
+        *hooks.events.lock().unwrap()
+    );
+    Ok(())
+}
+
+/// Doubles each count before it's processed
+#[derive(Debug)]
+struct DoublingMiddleware;
+
+impl ReplMiddleware<usize> for DoublingMiddleware {
+    fn call<'a>(&'a self, command: usize, next: Next<'a, usize>) -> BoxFuture<'a, Result<()>> {
+        next.run(command * 2)
+    }
+}
+
+/// Denies odd counts
+#[derive(Debug)]
+struct EvenMiddleware;
+
+impl ReplMiddleware<usize> for EvenMiddleware {
+    fn call<'a>(&'a self, command: usize, next: Next<'a, usize>) -> BoxFuture<'a, Result<()>> {
+        if command % 2 == 1 {
+            return Box::pin(std::future::ready(Err(StatusError::cancelled().into())));
+        }
+        next.run(command)
+    }
+}
+
+#[test]
+fn test_middleware_order() -> Result<()> {
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_middleware(EvenMiddleware)
+        .with_middleware(DoublingMiddleware)
+        .with_middleware(TimingMiddleware::default())
+        .with_middleware(AuditMiddleware)
+        .with_script("count 3\ncount 4\n".as_bytes());
+    repl.process()?;
+    assert_eq!(8, count.load(Ordering::SeqCst));
+
+    // the outermost layer sees the command first
+    count.store(0, Ordering::SeqCst);
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_middleware(DoublingMiddleware)
+        .with_middleware(EvenMiddleware)
+        .with_script("count 3\ncount 4\n".as_bytes());
+    repl.process()?;
+    assert_eq!(14, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+impl AsyncReplCommandProcessor<usize> for CountingProcessor {
+    async fn process_command(&self, command: usize) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<usize>::is_quit(self, command)
+    }
+}
+
+#[test]
+fn test_confirm_middleware() -> Result<()> {
+    // `--yes` skips the question, so no answer follows it
+    const SCRIPT: &str = "count 10\nn\ncount 7\ny\ncount 1\ncount 20 --yes\ncount 3\n";
+
+    let processor = CountingProcessor::default();
+    let count = processor.count.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_middleware(ConfirmMiddleware::new(|times: &usize| *times > 5))
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(31, count.load(Ordering::SeqCst));
+
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_middleware(ConfirmMiddleware::new(|times: &usize| *times > 5))
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script(SCRIPT.as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(62, count.load(Ordering::SeqCst));
+
+    // a declined command has status 1, like one declined by `requires_confirmation`, and ends
+    // the session with `:set -e`
+    count.store(0, Ordering::SeqCst);
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_middleware(ConfirmMiddleware::new(|times: &usize| *times > 5))
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script("count 10\nn\ncount $?\n:set -e\ncount 10\nn\ncount 1\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!((1, 1), (repl.status(), count.load(Ordering::SeqCst)));
+    Ok(())
+}
+
+/// Counts like [CountingProcessor], but counting more than 5 at once requires confirmation
+#[derive(Debug, Default, Clone)]
+struct CautiousProcessor {
+    inner: CountingProcessor,
+}
+
+impl ReplCommandProcessor<usize> for CautiousProcessor {
+    fn process_command(&self, command: usize) -> Result<()> {
+        ReplCommandProcessor::process_command(&self.inner, command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<usize>::is_quit(&self.inner, command)
+    }
+
+    fn requires_confirmation(&self, command: &usize) -> bool {
+        *command > 5
+    }
+}
+
+impl AsyncReplCommandProcessor<usize> for CautiousProcessor {
+    async fn process_command(&self, command: usize) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<usize>::is_quit(self, command)
+    }
+
+    fn requires_confirmation(&self, command: &usize) -> bool {
+        ReplCommandProcessor::requires_confirmation(self, command)
+    }
+}
+
+#[test]
+fn test_requires_confirmation() -> Result<()> {
+    const SCRIPT: &str = "count 10\ncount 1\ncount 7 --yes\n";
+
+    // scripts decline by default, but --yes skips the question
+    let processor = CautiousProcessor::default();
+    let count = processor.inner.count.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(8, count.load(Ordering::SeqCst));
+
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_script_confirmation(ScriptConfirmation::Accept)
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(26, count.load(Ordering::SeqCst));
+
+    // the answers are read from the script, and aren't parsed as commands
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script("count 10\nyes\ncount 6\nno\n".as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(36, count.load(Ordering::SeqCst));
+    Ok(())
+}
+
+/// Asks a question of each kind per command, recording the answers
+#[derive(Debug, Default, Clone)]
+struct QuestionProcessor {
+    answers: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReplCommandProcessor<usize> for QuestionProcessor {
+    fn process_command(&self, _command: usize) -> Result<()> {
+        Err(anyhow::anyhow!("Expected to be processed with a console"))
+    }
+
+    fn process_command_with(&self, _command: usize, console: &Console) -> Result<()> {
+        let name = console.ask_blocking("Name: ")?;
+        let password = console.password_blocking("Password: ")?;
+        let region = console.select_blocking("Region", &["us", "eu", "ap"])?;
+        let confirmed = console.confirm_blocking("Save?")?;
+        self.answers
+            .lock()
+            .unwrap()
+            .push(format!("{} {} {} {}", name, password, region, confirmed));
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+impl AsyncReplCommandProcessor<usize> for QuestionProcessor {
+    async fn process_command(&self, command: usize) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    async fn process_command_with(&self, _command: usize, console: &Console) -> Result<()> {
+        let name = console.ask("Name: ").await?;
+        let password = console.password("Password: ").await?;
+        let region = console.select("Region", &["us", "eu", "ap"]).await?;
+        let confirmed = console.confirm("Save?").await?;
+        self.answers
+            .lock()
+            .unwrap()
+            .push(format!("{} {} {} {}", name, password, region, confirmed));
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<usize>::is_quit(self, command)
+    }
+}
+
+#[test]
+fn test_console_questions() -> Result<()> {
+    // the answers are read from the script, accepting an option's number or name
+    const SCRIPT: &str = "count 1\nalice\nhunter2\n3\ny\ncount 1\nbob\nswordfish\nEU\nn\n";
+
+    let processor = QuestionProcessor::default();
+    let answers = processor.answers.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(
+        vec!["alice hunter2 2 true", "bob swordfish 1 false"],
+        *answers.lock().unwrap()
+    );
+
+    answers.lock().unwrap().clear();
+    let mut repl = Repl::from_parser(TimesParser, processor.clone(), None, None)?
+        .with_script_confirmation(ScriptConfirmation::Read)
+        .with_script(SCRIPT.as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(2, answers.lock().unwrap().len());
+
+    // an invalid choice can't be asked again of a script
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_script("count 1\nalice\nhunter2\n4\n".as_bytes());
+    assert!(repl.process().is_err());
+    Ok(())
+}
+
+/// Writes each count to the console's output, and warns of large counts on its diagnostics
+#[derive(Debug, Default)]
+struct WritingProcessor;
+
+impl ReplCommandProcessor<usize> for WritingProcessor {
+    fn process_command(&self, _command: usize) -> Result<()> {
+        Err(anyhow::anyhow!("Expected to be processed with a console"))
+    }
+
+    fn process_command_with(&self, command: usize, console: &Console) -> Result<()> {
+        writeln!(console.out(), "counted {}", command)?;
+        if command > 5 {
+            writeln!(console.err(), "that's a lot")?;
+        }
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+impl AsyncReplCommandProcessor<usize> for WritingProcessor {
+    async fn process_command(&self, command: usize) -> Result<()> {
+        ReplCommandProcessor::process_command(self, command)
+    }
+
+    async fn process_command_with(&self, command: usize, console: &Console) -> Result<()> {
+        ReplCommandProcessor::process_command_with(self, command, console)
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        ReplCommandProcessor::<usize>::is_quit(self, command)
+    }
+}
+
+#[test]
+fn test_output_capture() -> Result<()> {
+    const SCRIPT: &str = "count 3\nhelp\ncount 8\n:pin\n";
+
+    let out = OutputBuffer::default();
+    let err = OutputBuffer::default();
+    let transcript = OutputBuffer::default();
+    let output = ReplOutput::default()
+        .redirect(out.clone())
+        .redirect_err(err.clone())
+        .tee(transcript.clone());
+    let mut repl = Repl::from_parser(TimesParser, WritingProcessor, None, None)?
+        .with_output(output.clone())
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(
+        "counted 3\ncount <n>\ncounted 8\nNo arguments are pinned\n",
+        out.take()
+    );
+    assert_eq!("that's a lot\n", err.take());
+    assert_eq!(
+        "counted 3\ncount <n>\ncounted 8\nthat's a lot\nNo arguments are pinned\n",
+        transcript.take()
+    );
+
+    let mut repl = Repl::from_parser(TimesParser, WritingProcessor, None, None)?
+        .with_output(output)
+        .with_script(SCRIPT.as_bytes());
+    futures::executor::block_on(repl.process_async())?;
+    assert_eq!(
+        "counted 3\ncount <n>\ncounted 8\nNo arguments are pinned\n",
+        out.contents()
+    );
+    assert_eq!("that's a lot\n", err.contents());
+    Ok(())
+}
+
+#[test]
+fn test_redirect_after_tee() {
+    // redirecting keeps the transcript teed before it
+    let out = OutputBuffer::default();
+    let err = OutputBuffer::default();
+    let transcript = OutputBuffer::default();
+    let output = ReplOutput::default()
+        .tee(transcript.clone())
+        .redirect(out.clone())
+        .redirect_err(err.clone());
+    writeln!(output.out(), "3 rows").unwrap();
+    writeln!(output.err(), "1 warning").unwrap();
+    assert_eq!(
+        ("3 rows\n", "1 warning\n"),
+        (out.take().as_str(), err.take().as_str())
+    );
+    assert_eq!("3 rows\n1 warning\n", transcript.take());
+}
+
+#[derive(serde::Serialize)]
+struct Row {
+    name: String,
+    rows: usize,
+}
+
+/// Renders the requested number of rows as its result
+#[derive(Debug, Default)]
+struct RowsProcessor;
+
+impl ReplCommandProcessor<usize> for RowsProcessor {
+    fn process_command(&self, _command: usize) -> Result<()> {
+        Err(anyhow::anyhow!("Expected to be processed with a console"))
+    }
+
+    fn process_command_result(
+        &self,
+        command: usize,
+        _console: &Console,
+    ) -> Result<Option<Box<dyn Render>>> {
+        let rows: Vec<Row> = (1..=command)
+            .map(|index| Row {
+                name: format!("table{}", index),
+                rows: index * 10,
+            })
+            .collect();
+        Ok(Some(Box::new(rows)))
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+#[test]
+fn test_result_formats() -> Result<()> {
+    const SCRIPT: &str =
+        "count 2\n:format json\ncount 1\n:format YAML\ncount 1\n:format csv\n:format\n";
+
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(TimesParser, RowsProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(
+        "name    rows\n------  ----\ntable1  10\ntable2  20\n\
+         [\n  {\n    \"name\": \"table1\",\n    \"rows\": 10\n  }\n]\n\
+         - name: table1\n  rows: 10\n\
+         yaml\n",
+        out.take()
+    );
+
+    let mut repl = Repl::from_parser(TimesParser, RowsProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_format(OutputFormat::Raw)
+        .with_script("count 2\n".as_bytes());
+    repl.process()?;
+    assert_eq!(
+        "{\"name\":\"table1\",\"rows\":10}\n{\"name\":\"table2\",\"rows\":20}\n",
+        out.take()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_redirection() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("tables.txt");
+    let script = format!(
+        "count 5 | grep table | head 2\n\
+         count 3 | count\n\
+         count 1 | grep -v table\n\
+         count 2 > {file}\n\
+         count 1 >> {file}\n\
+         count 2 | tail 1 >> {file}\n\
+         count 1 | sort\n\
+         count 1\n",
+        file = file.display()
+    );
+
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(TimesParser, RowsProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(std::io::Cursor::new(script));
+    repl.process()?;
+    assert_eq!(
+        "table1  10\ntable2  20\n\
+         5\n\
+         name    rows\n------  ----\n\
+         name    rows\n------  ----\ntable1  10\n",
+        out.take()
+    );
+    assert_eq!(
+        "name    rows\n------  ----\ntable1  10\ntable2  20\n\
+         name    rows\n------  ----\ntable1  10\n\
+         table2  20\n",
+        std::fs::read_to_string(&file)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_paged_output_before_questions() -> Result<()> {
+    let (out, transcript) = (OutputBuffer::default(), OutputBuffer::default());
+    let mut repl = Repl::from_parser(TimesParser, QuestionProcessor::default(), None, None)?
+        .with_output(
+            ReplOutput::default()
+                .redirect(out.clone())
+                .tee(transcript.clone()),
+        )
+        .with_script("Ada\n".as_bytes());
+
+    // output held to page it is written out before a question is asked
+    repl.begin_redirection(Some(Redirection::default().paged()));
+    let console = repl.console(false);
+    writeln!(console.out(), "summary")?;
+    assert_eq!("", out.contents());
+    assert_eq!("Ada", console.ask_blocking("Name: ")?);
+    assert_eq!("summary\n", out.take());
+    writeln!(console.out(), "details")?;
+    assert_eq!("", out.contents());
+
+    // filtered output is never written early
+    repl.end_redirection();
+    repl.begin_redirection(Some(
+        Redirection::split(&["|", "count"]).1.unwrap().unwrap(),
+    ));
+    writeln!(repl.console(false).out(), "summary")?;
+    assert!(repl.console(false).ask_blocking("Name: ").is_err());
+    assert_eq!("", out.contents());
+
+    // the pager copies what it pages to any transcript, but not to the output itself
+    repl.end_redirection();
+    let mut copies = repl.output.out().copies();
+    writeln!(copies, "paged")?;
+    assert_eq!("", out.contents());
+    assert_eq!("summary\npaged\n", transcript.take());
+    Ok(())
+}
+
+#[test]
+fn test_pager_skips_scripts() -> Result<()> {
+    // scripts are never paged, so output is written as it would be without the pager
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(TimesParser, RowsProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_pager()
+        .with_script("count 100\ncount 3 | count\n".as_bytes());
+    assert!(!repl.pages());
+    repl.process()?;
+    let output = out.take();
+    assert_eq!(103, output.lines().count());
+    assert!(output.ends_with("table100  1000\n5\n"));
+    Ok(())
+}
+
+/// Either lists rows as a result, or echoes its argument
+#[derive(Debug)]
+enum Lookup {
+    Rows(usize),
+    Echo(String),
+}
+
+#[derive(Debug)]
+struct LookupParser;
+
+impl CommandParser<Lookup> for LookupParser {
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<Lookup> {
+        match tokens {
+            ["rows", count] => count
+                .parse()
+                .map(|count| ParseOutcome::Command(Lookup::Rows(count)))
+                .unwrap_or_else(|err| ParseOutcome::Error(format!("Invalid count: {}", err))),
+            ["echo", text] => ParseOutcome::Command(Lookup::Echo(text.to_string())),
+            _ => ParseOutcome::Error("Unknown command".to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LookupProcessor;
+
+impl ReplCommandProcessor<Lookup> for LookupProcessor {
+    fn process_command(&self, _command: Lookup) -> Result<()> {
+        Err(anyhow::anyhow!("Expected to be processed with a console"))
+    }
+
+    fn process_command_with(&self, command: Lookup, console: &Console) -> Result<()> {
+        match command {
+            Lookup::Rows(count) => match RowsProcessor.process_command_result(count, console)? {
+                Some(rows) => console.render(&*rows),
+                None => Ok(()),
+            },
+            Lookup::Echo(text) => Ok(writeln!(console.out(), "{}", text)?),
+        }
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+#[test]
+fn test_result_references() -> Result<()> {
+    const SCRIPT: &str = "rows 2\n\
+                          echo $_.1.name\n\
+                          echo $_1.0.rows\n\
+                          rows 1\n\
+                          echo $_\n\
+                          echo $_2\n\
+                          echo $_.5\n\
+                          :results\n";
+
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(LookupParser, LookupProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_format(OutputFormat::Json)
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    let rows = "[\n  {\n    \"name\": \"table1\",\n    \"rows\": 10\n  }";
+    assert_eq!(
+        format!(
+            "{rows},\n  {{\n    \"name\": \"table2\",\n    \"rows\": 20\n  }}\n]\n\
+             table2\n\
+             10\n\
+             {rows}\n]\n\
+             [{{\"name\":\"table1\",\"rows\":10}}]\n\
+             $_1  [{{\"name\":\"table1\",\"rows\":10}},{{\"name\":\"table2\",\"rows\":20}}]\n\
+             $_4  [{{\"name\":\"table1\",\"rows\":10}}]\n",
+            rows = rows
+        ),
+        out.take()
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+enum Outcome {
+    Echo(String),
+    Fail(Option<i32>),
+}
+
+#[derive(Debug)]
+struct OutcomeParser;
+
+impl CommandParser<Outcome> for OutcomeParser {
+    fn parse(&mut self, tokens: &[&str]) -> ParseOutcome<Outcome> {
+        match tokens {
+            ["echo", text] => ParseOutcome::Command(Outcome::Echo(text.to_string())),
+            ["fail"] => ParseOutcome::Command(Outcome::Fail(None)),
+            ["fail", code] => code
+                .parse()
+                .map(|code| ParseOutcome::Command(Outcome::Fail(Some(code))))
+                .unwrap_or_else(|err| ParseOutcome::Error(format!("Invalid code: {}", err))),
+            _ => ParseOutcome::Error("Unknown command".to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct OutcomeProcessor;
+
+impl ReplCommandProcessor<Outcome> for OutcomeProcessor {
+    fn process_command(&self, _command: Outcome) -> Result<()> {
+        Err(anyhow::anyhow!("Expected to be processed with a console"))
+    }
+
+    fn process_command_with(&self, command: Outcome, console: &Console) -> Result<()> {
+        match command {
+            Outcome::Echo(text) => Ok(writeln!(console.out(), "{}", text)?),
+            Outcome::Fail(None) => Err(anyhow::anyhow!("Failed")),
+            Outcome::Fail(Some(code)) => {
+                Err(anyhow::Error::new(StatusError::new(code, "Failed")).context("While failing"))
+            }
+        }
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit")
+    }
+}
+
+#[test]
+fn test_exit_status() -> Result<()> {
+    const SCRIPT: &str = ":set +e\n\
+                          echo $?\n\
+                          fail\n\
+                          echo $?\n\
+                          fail 3\n\
+                          echo code=$?\n\
+                          bogus\n\
+                          echo $?\n\
+                          echo done\n\
+                          :set\n\
+                          fail 4\n";
+
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!("0\n1\ncode=3\n2\ndone\n+e +x\n", out.take());
+    assert_eq!(4, repl.status());
+
+    // failures end the session by default, or once `:set -e` is on again
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script("fail 5\necho unreachable\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!(5, repl.status());
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(":set +e\nfail\n:set -e\nfail\necho unreachable\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!("", out.take());
+
+    // with `:set -e`, invalid lines and cancelled commands end the session too
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script("bogus\n:set -e\nbogus\necho after-invalid\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!((2, ""), (repl.status(), out.take().as_str()));
+    let processor = CautiousProcessor::default();
+    let count = processor.inner.count.clone();
+    let mut repl = Repl::from_parser(TimesParser, processor, None, None)?
+        .with_script("count 10\n:set -e\ncount 10\ncount 1\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert_eq!((1, 0), (repl.status(), count.load(Ordering::SeqCst)));
+
+    // as do invalid lines within a context, which otherwise only set the status
+    let processor = DbProcessor::default();
+    let counted = processor.counted.clone();
+    let mut repl = Repl::<DbCommand, _>::from_subcommand(processor.clone(), None, None)?
+        .with_script("use orders\nbogus\n".as_bytes());
+    repl.process()?;
+    assert_eq!(2, repl.status());
+    let mut repl = Repl::<DbCommand, _>::from_subcommand(processor, None, None)?
+        .with_script("use orders\n:set -e\nbogus\ncount\n".as_bytes());
+    assert!(repl.process().is_err());
+    assert!(counted.lock().unwrap().is_empty());
+
+    // the prompt shows the status
+    let mut repl = Repl::from_parser(
+        OutcomeParser,
+        OutcomeProcessor,
+        None,
+        Some("[$?] $ ".into()),
+    )?
+    .with_output(ReplOutput::default().redirect(out.clone()))
+    .with_script(":set +e\nfail 7\n".as_bytes());
+    assert_eq!("[0] $ ", repl.current_prompt());
+    repl.process()?;
+    assert_eq!("[7] $ ", repl.current_prompt());
+    Ok(())
+}
+
+#[test]
+fn test_meta_command_status() -> Result<()> {
+    const SCRIPT: &str = ":set +e\n\
+                          bogus\n\
+                          :format raw\n\
+                          echo $?\n\
+                          :format bogus\n\
+                          echo $?\n";
+
+    // meta-commands set the status like any other line
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!("0\n2\n", out.take());
+
+    // and with `:set -e`, an invalid one ends the session
+    for line in [
+        ":format bogus",
+        ":pin prod",
+        ":unpin --env",
+        ":default --env prod",
+        ":undefault echo",
+        ":set -q",
+    ] {
+        let script = format!(":set -e\n{}\necho unreachable\n", line);
+        let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+            .with_output(ReplOutput::default().redirect(out.clone()))
+            .with_script(std::io::Cursor::new(script));
+        assert!(repl.process().is_err(), "{}", line);
+        assert_eq!((2, ""), (repl.status(), out.take().as_str()), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_xtrace() -> Result<()> {
+    const SCRIPT: &str = "echo quiet\n\
+                          :set -x\n\
+                          echo $?\n\
+                          :set +x\n\
+                          echo quiet\n";
+
+    let (out, err) = (OutputBuffer::default(), OutputBuffer::default());
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(
+            ReplOutput::default()
+                .redirect(out.clone())
+                .redirect_err(err.clone()),
+        )
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!("quiet\n0\nquiet\n", out.take());
+    assert_eq!("+ echo 0\n", err.take());
+    Ok(())
+}
+
+#[test]
+fn test_watch_parsing() {
+    let watch = Watch::parse(&["-n", "5", "echo", "status"]).unwrap();
+    assert_eq!(std::time::Duration::from_secs(5), watch.interval());
+    assert_eq!(["echo", "status"], watch.command());
+    assert_eq!("Every 5s: echo status    (Ctrl-C to stop)", watch.header());
+
+    let watch = Watch::parse(&["-n0.5", "echo", "status"]).unwrap();
+    assert_eq!(std::time::Duration::from_millis(500), watch.interval());
+    let watch = Watch::parse(&["echo", "status"]).unwrap();
+    assert_eq!(std::time::Duration::from_secs(2), watch.interval());
+    let watch = Watch::parse(&["-n", "0", "echo", "status"]).unwrap();
+    assert_eq!(std::time::Duration::from_millis(100), watch.interval());
+
+    assert!(Watch::parse(&[]).is_err());
+    assert!(Watch::parse(&["-n"]).is_err());
+    assert!(Watch::parse(&["-n", "5"]).is_err());
+    assert!(Watch::parse(&["-n", "soon", "echo", "status"]).is_err());
+    assert!(Watch::parse(&["-n", "-1", "echo", "status"]).is_err());
+}
+
+#[test]
+fn test_watch_highlighting() {
+    use crossterm::style::Attribute;
+
+    let lines = |lines: &[&str]| {
+        lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+    };
+    let previous = lines(&["orders  42", "customers  7"]);
+    let current = lines(&["orders  43", "customers  7", "new"]);
+    assert_eq!(current, watch::highlight(None, &current));
+    let (on, off) = (Attribute::Reverse, Attribute::NoReverse);
+    assert_eq!(
+        vec![
+            format!("orders  4{on}3{off}"),
+            "customers  7".to_string(),
+            format!("{on}new{off}"),
+        ],
+        watch::highlight(Some(&previous), &current)
+    );
+}
+
+#[test]
+fn test_watch_requires_terminal() -> Result<()> {
+    const SCRIPT: &str = ":set +e\n\
+                          watch -n 1 echo status\n\
+                          echo $?\n\
+                          watch -n 1\n\
+                          echo $?\n";
+
+    let out = OutputBuffer::default();
+    let mut repl = Repl::from_parser(OutcomeParser, OutcomeProcessor, None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!("1\n2\n", out.take());
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+enum WatchlistCommand {
+    /// Add a name to the watchlist
+    Watch { name: String },
+}
+
+#[derive(Debug, Default, Clone)]
+struct WatchlistProcessor {
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReplCommandProcessor<WatchlistCommand> for WatchlistProcessor {
+    fn process_command(&self, command: WatchlistCommand) -> Result<()> {
+        match command {
+            WatchlistCommand::Watch { name } => self.names.lock().unwrap().push(name),
+        }
+        Ok(())
+    }
+
+    fn is_quit(&self, command: &str) -> bool {
+        matches!(command, "quit" | "exit")
+    }
+}
+
+#[test]
+fn test_watch_command_takes_precedence() -> Result<()> {
+    const SCRIPT: &str = "watch orders\n\
+                          watch -n 1 orders\n";
+
+    // the application's `watch` runs rather than the built-in, so an interval is an invalid
+    // argument (2) rather than a watch which can't run from a script (1)
+    let processor = WatchlistProcessor::default();
+    let out = OutputBuffer::default();
+    let mut repl = Repl::<WatchlistCommand, _>::from_subcommand(processor.clone(), None, None)?
+        .with_output(ReplOutput::default().redirect(out.clone()))
+        .with_script(SCRIPT.as_bytes());
+    repl.process()?;
+    assert_eq!(vec!["orders".to_string()], *processor.names.lock().unwrap());
+    assert_eq!(2, repl.status());
+    Ok(())
+}
@@ -0,0 +1,190 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Watching a command, re-running it periodically (`watch -n 5 status --all`) and redrawing
+//! its output with what changed since the last run highlighted, until Ctrl-C is pressed
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Attribute;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// The time between runs when `-n` isn't supplied
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The shortest time allowed between runs
+const MINIMUM_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A command to watch, and how often it's run
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Watch {
+    interval: Duration,
+    command: Vec<String>,
+}
+
+impl Watch {
+    /// Parse the arguments following `watch`, i.e. `-n 5 status --all`
+    pub(crate) fn parse(tokens: &[&str]) -> Result<Self, String> {
+        let (seconds, command) = match tokens {
+            ["-n", seconds, command @ ..] => (Some(*seconds), command),
+            ["-n"] => return Err("Expected a number of seconds after '-n'".to_string()),
+            [option, command @ ..] if option.starts_with("-n") => (Some(&option[2..]), command),
+            command => (None, command),
+        };
+        let interval = match seconds {
+            Some(seconds) => seconds
+                .parse()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or_else(|| format!("Expected a number of seconds, found '{}'", seconds))?
+                .max(MINIMUM_INTERVAL),
+            None => DEFAULT_INTERVAL,
+        };
+        if command.is_empty() {
+            return Err("Expected a command to watch".to_string());
+        }
+        Ok(Self {
+            interval,
+            command: command.iter().map(|token| token.to_string()).collect(),
+        })
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub(crate) fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// The line above the command's output
+    pub(crate) fn header(&self) -> String {
+        format!(
+            "Every {}s: {}    (Ctrl-C to stop)",
+            self.interval.as_secs_f64(),
+            self.command.join(" ")
+        )
+    }
+}
+
+/// The lines, with the characters which differ from the previous run's lines reversed. Nothing
+/// is highlighted on the first run
+pub(crate) fn highlight(previous: Option<&[String]>, lines: &[String]) -> Vec<String> {
+    let Some(previous) = previous else {
+        return lines.to_vec();
+    };
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let before: Vec<char> = previous
+                .get(index)
+                .map(|line| line.chars().collect())
+                .unwrap_or_default();
+            let mut highlighted = String::new();
+            let mut reversed = false;
+            for (column, character) in line.chars().enumerate() {
+                let changed = before.get(column) != Some(&character);
+                if changed != reversed {
+                    let attribute = if changed {
+                        Attribute::Reverse
+                    } else {
+                        Attribute::NoReverse
+                    };
+                    highlighted.push_str(&attribute.to_string());
+                    reversed = changed;
+                }
+                highlighted.push(character);
+            }
+            if reversed {
+                highlighted.push_str(&Attribute::NoReverse.to_string());
+            }
+            highlighted
+        })
+        .collect()
+}
+
+/// The watched command's screen, remembering the last run's output to highlight changes
+pub(crate) struct Screen {
+    header: String,
+    previous: Option<Vec<String>>,
+}
+
+impl Screen {
+    pub(crate) fn new(watch: &Watch) -> Self {
+        Self {
+            header: watch.header(),
+            previous: None,
+        }
+    }
+
+    /// Clear the screen and draw the run's output beneath the header, cut to the terminal's
+    /// height
+    pub(crate) fn draw(&mut self, lines: Vec<String>) -> std::io::Result<()> {
+        let (_, height) = terminal::size()?;
+        let mut stdout = std::io::stdout();
+        queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+        // raw mode doesn't return the cursor to the start of the line
+        write!(stdout, "{}\r\n\r\n", self.header)?;
+        let shown = usize::from(height).saturating_sub(2);
+        for line in highlight(self.previous.as_deref(), &lines)
+            .iter()
+            .take(shown)
+        {
+            write!(stdout, "{}\r\n", line)?;
+        }
+        stdout.flush()?;
+        self.previous = Some(lines);
+        Ok(())
+    }
+}
+
+/// The terminal in raw mode on the alternate screen while a command is watched, so Ctrl-C is
+/// read as a key rather than ending the session. The terminal is restored when it's dropped
+pub(crate) struct Terminal;
+
+impl Terminal {
+    pub(crate) fn enter() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let terminal = Self;
+        execute!(std::io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Wait out the interval before the next run, returning whether the user stopped watching
+/// with Ctrl-C (or `q`) in the meantime
+pub(crate) fn wait(interval: Duration) -> std::io::Result<bool> {
+    let deadline = Instant::now() + interval;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || !event::poll(remaining)? {
+            return Ok(false);
+        }
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        {
+            match code {
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(true),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Runtime-agnostic execution of blocking work (i.e. reading a line from the terminal)
+//! for the asynchronous REPL loop. Only [std] futures are used, so any executor
+//! (tokio, async-std, smol, futures, ...) can drive [crate::Repl::process_async]
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A unit of blocking work handed to a [BlockingSpawner]
+pub type BlockingJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Spawns blocking work somewhere it won't stall the async executor.
+///
+/// The default, [ThreadSpawner], runs each job on a fresh OS thread which works with every
+/// executor. Any closure taking a [BlockingJob] is also a spawner, so a runtime's own
+/// blocking pool can be plugged in directly, e.g.
+///
+/// ```ignore
+/// repl.with_blocking_spawner(|job| { tokio::task::spawn_blocking(job); })
+/// repl.with_blocking_spawner(|job| { async_std::task::spawn_blocking(job); })
+/// repl.with_blocking_spawner(|job| smol::unblock(job).detach())
+/// ```
+pub trait BlockingSpawner: Send + Sync {
+    /// Run the job to completion off of the async executor. If the job is dropped without
+    /// being run, the REPL loop will terminate with an error
+    fn spawn_blocking(&self, job: BlockingJob);
+}
+
+impl<F> BlockingSpawner for F
+where
+    F: Fn(BlockingJob) + Send + Sync,
+{
+    fn spawn_blocking(&self, job: BlockingJob) {
+        (self)(job)
+    }
+}
+
+/// Runs every blocking job on a newly spawned [std::thread]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadSpawner;
+
+impl BlockingSpawner for ThreadSpawner {
+    fn spawn_blocking(&self, job: BlockingJob) {
+        std::thread::spawn(job);
+    }
+}
+
+/// Holder of the [BlockingSpawner] within the REPL, so the REPL can remain [std::fmt::Debug]
+#[derive(Clone)]
+pub(crate) struct Spawner(Arc<dyn BlockingSpawner>);
+
+impl std::fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Spawner")
+    }
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Self(Arc::new(ThreadSpawner))
+    }
+}
+
+impl Spawner {
+    pub(crate) fn new<S: BlockingSpawner + 'static>(spawner: S) -> Self {
+        Self(Arc::new(spawner))
+    }
+
+    /// Run the supplied function on the spawner, resolving to its output once complete
+    pub(crate) fn run<F, T>(&self, work: F) -> Completion<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            value: None,
+            waker: None,
+            finished: false,
+        }));
+        let sender = Sender(shared.clone());
+        self.0.spawn_blocking(Box::new(move || sender.send(work())));
+        Completion(shared)
+    }
+}
+
+struct Shared<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    finished: bool,
+}
+
+/// The sending half of a [Completion], which marks it finished when dropped
+/// so a job discarded by the spawner doesn't hang the REPL forever
+struct Sender<T>(Arc<Mutex<Shared<T>>>);
+
+impl<T> Sender<T> {
+    fn send(self, value: T) {
+        if let Ok(mut shared) = self.0.lock() {
+            shared.value = Some(value);
+        }
+        // drop(self) wakes the receiver
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = match self.0.lock() {
+            Ok(mut shared) => {
+                shared.finished = true;
+                shared.waker.take()
+            }
+            Err(_) => None,
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Wakes the thread blocked in [block_on]
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drive the future to completion on the current thread, which is how the synchronous REPL
+/// loop runs futures such as the middleware chain
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// A future resolving to the output of a job run on a [BlockingSpawner]
+pub(crate) struct Completion<T>(Arc<Mutex<Shared<T>>>);
+
+impl<T> Future for Completion<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = match self.0.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Ready(Err(anyhow::anyhow!("Blocking job panicked"))),
+        };
+        if let Some(value) = shared.value.take() {
+            Poll::Ready(Ok(value))
+        } else if shared.finished {
+            Poll::Ready(Err(anyhow::anyhow!(
+                "Blocking job was dropped before completing"
+            )))
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
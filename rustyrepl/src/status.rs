@@ -0,0 +1,88 @@
+// Copyright (c) Sean Lawlor
+//
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! The exit status of the last line, read with `$?`
+
+/// The status of a line which succeeded
+pub(crate) const SUCCESS: i32 = 0;
+
+/// The status of a line which failed, or was denied or cancelled
+pub(crate) const FAILURE: i32 = 1;
+
+/// The status of a line which wasn't a valid command
+pub(crate) const INVALID: i32 = 2;
+
+/// An error setting the exit status (`$?`) to a specific code, rather than 1. It may be
+/// returned directly, or anywhere within an error's chain of causes.
+///
+/// ```
+/// use anyhow::Result;
+/// use rustyrepl::StatusError;
+///
+/// fn check(healthy: bool) -> Result<()> {
+///     if !healthy {
+///         return Err(StatusError::new(3, "The service is degraded").into());
+///     }
+///     Ok(())
+/// }
+/// assert!(check(false).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusError {
+    code: i32,
+    message: String,
+    /// Whether the command was denied or cancelled, rather than failing
+    cancelled: bool,
+}
+
+impl StatusError {
+    /// Construct an error with the exit status and message
+    pub fn new<S: Into<String>>(code: i32, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            cancelled: false,
+        }
+    }
+
+    /// The error of a command which was denied or cancelled (i.e. by a [crate::ReplMiddleware])
+    /// rather than processed, with status 1. Like a declined confirmation, it only ends the
+    /// session when `:set -e` is on
+    pub fn cancelled() -> Self {
+        Self {
+            code: FAILURE,
+            message: "Command cancelled".to_string(),
+            cancelled: true,
+        }
+    }
+
+    /// The exit status
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// The exit status of a failed command, from the first [StatusError] within the error's
+/// chain, or otherwise [FAILURE]
+pub(crate) fn status_of(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<StatusError>())
+        .map_or(FAILURE, StatusError::code)
+}
+
+/// Whether the error is a command being denied or cancelled, from [StatusError::cancelled]
+pub(crate) fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<StatusError>())
+        .any(|status| status.cancelled)
+}